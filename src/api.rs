@@ -1,33 +1,287 @@
+use std::io::Read;
+
 use serde::{Deserialize, Serialize};
 use tiny_http::Request;
 
+use crate::config::{self, PolicyMode};
+use crate::error::Error;
 use crate::logger::get_reporter;
-use crate::utils::{is_zero, now, one, week_seconds, Result};
+use crate::multipart;
+use crate::utils::{is_zero, is_zero_u32, is_zero_u64, now, parse_query, parse_timestamp, Result, ResultV};
 
 
 const MODULE: &str = "API";
 
+const MIME_OCTET_STREAM: &str = "application/octet-stream";
+/// File name and MIME type are stored in `VARCHAR(255)` columns by MySQL
+const MAX_FILE_FIELD_LENGTH: usize = 255;
+
 
 #[derive(Deserialize)]
 pub struct ApiAddRequest {
     data: String,
 
-    #[serde(default = "one")]
-    max_clicks: u32,
+    #[serde(default)]
+    max_clicks: Option<u32>,
 
-    #[serde[default = "week_seconds"]]
-    lifetime: u64,
+    #[serde(default)]
+    lifetime: Option<u64>,
+
+    /// Absolute expiry time, alternative to `lifetime`
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    expires_at: Option<i64>,
+
+    /// Secret can not be retrieved before this time
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    not_before: Option<i64>,
+
+    #[serde(default)]
+    passphrase: Option<String>,
+
+    #[serde(skip)]
+    file: Option<ApiFile>,
+}
+
+/// Timestamp in JSON request is either unix time or a string with unix time or RFC 3339 date
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApiTimestamp {
+    Unix(i64),
+    Text(String),
+}
+
+fn deserialize_timestamp<'de, D>(deserializer: D) -> std::result::Result<Option<i64>, D::Error>
+where D: serde::Deserializer<'de> {
+    use serde::de::Error;
+    match Option::<ApiTimestamp>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(ApiTimestamp::Unix(ts)) => Ok(Some(ts)),
+        Some(ApiTimestamp::Text(s)) => parse_timestamp(&s).map(Some).map_err(D::Error::custom),
+    }
+}
+
+/// Uploaded file, sent either as a part of multipart form or as raw request body
+struct ApiFile {
+    name: String,
+    mime: String,
+    content: Vec<u8>,
 }
 
 impl ApiAddRequest {
-    pub fn get_data(&self) -> &String { &self.data }
-    pub fn get_max_clicks(&self) -> u32 { if self.max_clicks <= 0 {one().into()} else {self.max_clicks} }
-    pub fn get_lifetime(&self) -> u64 { if self.lifetime <= 0 {week_seconds()} else {self.lifetime} }
+    /// Secret data: file content for uploaded files, `data` field otherwise
+    pub fn get_content(&self) -> &[u8] { self.file.as_ref().map_or(self.data.as_bytes(), |f| &f.content) }
+    pub fn get_filename(&self) -> &str { self.file.as_ref().map_or("", |f| &f.name) }
+    pub fn get_mime(&self) -> &str { self.file.as_ref().map_or("", |f| &f.mime) }
+    pub fn get_passphrase(&self) -> Option<&String> { self.passphrase.as_ref().filter(|p| !p.is_empty()) }
+    /// Filled by `apply_policy`, which has to be called before
+    pub fn get_max_clicks(&self) -> u32 { self.max_clicks.unwrap_or_default() }
+    /// Time in seconds the secret is available for, counted from `not_before` if it is set.
+    /// Filled by `apply_policy`, which has to be called before
+    pub fn get_lifetime(&self) -> u64 { self.lifetime.unwrap_or_default() }
+    pub fn get_not_before(&self) -> i64 { self.not_before.unwrap_or_default() }
+
+    /// Fill missing lifetime and clicks with policy defaults and clamp or reject values out of policy limits.
+    /// `expires_at` is turned into lifetime here, so policy limits apply to it as well
+    pub fn apply_policy(&mut self, policy: &config::Policy) -> ResultV {
+        // Zero means "use default" in clamp mode, but is not a valid value to be rejected silently
+        if policy.mode == PolicyMode::Reject {
+            if self.lifetime == Some(0) {
+                return Err(Error::Policy("Lifetime has to be positive"));
+            }
+            if self.max_clicks == Some(0) {
+                return Err(Error::Policy("Number of clicks has to be positive"));
+            }
+        }
+
+        let now = now();
+        // Activation time in the past means the secret is available right away
+        self.not_before = self.not_before.filter(|t| *t > now);
+        let start = self.not_before.unwrap_or(now);
+        // Secret is deleted not later than `max_lifetime` from now, waiting for activation included
+        let delay = (start - now) as u64;
+        if delay.saturating_add(policy.min_lifetime) > policy.max_lifetime {
+            return Err(Error::Policy("Activation time is too far in the future"));
+        }
+        let max_lifetime = policy.max_lifetime - delay;
+
+        let requested = match (self.lifetime.filter(|l| *l > 0), self.expires_at) {
+            (Some(_), Some(_)) => return Err(Error::Policy("Only one of `lifetime` and `expires_at` can be given")),
+            (_, Some(t)) if t <= start => return Err(Error::Policy("Expiry time is before activation time")),
+            (_, Some(t)) => Some((t - start) as u64),
+            (lifetime, None) => lifetime,
+        };
+        let lifetime = requested.unwrap_or(policy.default_lifetime.min(max_lifetime));
+        let max_clicks = self.max_clicks.filter(|c| *c > 0).unwrap_or(policy.default_max_clicks);
+
+        if policy.mode == PolicyMode::Reject {
+            if lifetime < policy.min_lifetime {
+                return Err(Error::Policy("Lifetime is shorter than allowed"));
+            }
+            if lifetime > max_lifetime {
+                return Err(Error::Policy("Lifetime is longer than allowed"));
+            }
+            if max_clicks > policy.max_clicks {
+                return Err(Error::Policy("Too many clicks requested"));
+            }
+        }
+        self.lifetime = Some(lifetime.clamp(policy.min_lifetime, max_lifetime));
+        self.expires_at = None;
+        self.max_clicks = Some(max_clicks.min(policy.max_clicks));
+        Ok(())
+    }
+
+    /// Parse JSON, `multipart/form-data` or `application/octet-stream` request depending on its content type.
+    /// Body is not read beyond `max_body_bytes`, and secret data can not be longer than `max_secret_bytes`
+    pub fn parse_from(r: &mut Request, cfg: &config::Server) -> Result<ApiAddRequest> {
+        let content_type = r.headers().iter()
+            .find(|h| h.field.equiv("Content-Type"))
+            .map(|h| h.value.to_string())
+            .unwrap_or_default();
+
+        let body = read_body(r, cfg.max_body_bytes)?;
+        let req = if let Some(boundary) = multipart::boundary(&content_type) {
+            Self::parse_multipart(&body, &boundary)?
+        } else if content_type.starts_with(MIME_OCTET_STREAM) {
+            Self::parse_octet_stream(r.url(), body)?
+        } else {
+            serde_json::from_slice(&body).map_err(
+                get_reporter(MODULE, "ApiAddRequest::parse_from", Error::parse)
+            )?
+        };
+
+        if req.get_content().len() > cfg.max_secret_bytes {
+            return Err(Error::PayloadTooLarge);
+        }
+        Ok(req)
+    }
 
-    pub fn parse_from(r: &mut Request) -> Result<ApiAddRequest> {
-        serde_json::from_reader(r.as_reader()).map_err(
-            get_reporter(MODULE, "ApiAddRequest::parse_from", "parse error")
-        )
+    fn empty() -> ApiAddRequest {
+        ApiAddRequest {
+            data: String::new(), max_clicks: None, lifetime: None, expires_at: None, not_before: None, passphrase: None, file: None,
+        }
+    }
+
+    /// Apply text field of multipart form or url query parameter
+    fn set_field(&mut self, name: &str, value: &str) -> Result<()> {
+        let number = |v: &str| v.trim().parse::<u64>().map_err(|e| Error::Parse(format!("`{}`: {}", name, e)));
+        match name {
+            "data" => self.data = value.to_string(),
+            "max_clicks" => self.max_clicks = Some(u32::try_from(number(value)?).map_err(Error::parse)?),
+            "lifetime" => self.lifetime = Some(number(value)?),
+            "expires_at" => self.expires_at = Some(parse_timestamp(value)?),
+            "not_before" => self.not_before = Some(parse_timestamp(value)?),
+            "passphrase" => self.passphrase = Some(value.to_string()),
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn parse_multipart(body: &[u8], boundary: &str) -> Result<ApiAddRequest> {
+        let mut req = Self::empty();
+        for part in multipart::parse(body, boundary)? {
+            match part.filename {
+                Some(name) => req.file = Some(ApiFile {
+                    name: file_basename(&name)?,
+                    mime: valid_mime(part.content_type.as_deref())?,
+                    content: part.data,
+                }),
+                None => {
+                    let value = String::from_utf8(part.data).map_err(Error::parse)?;
+                    req.set_field(&part.name, &value)?;
+                },
+            }
+        }
+        if req.file.is_none() && req.data.is_empty() {
+            return Err(Error::Parse("neither `file` nor `data` is given".to_string()));
+        }
+        Ok(req)
+    }
+
+    /// Raw body is the file content, other fields are taken from url query
+    fn parse_octet_stream(url: &str, body: Vec<u8>) -> Result<ApiAddRequest> {
+        let mut req = Self::empty();
+        let mut name = String::new();
+        let query = url.split_once('?').map_or("", |(_, q)| q);
+        for (field, value) in parse_query(query) {
+            match field.as_str() {
+                "filename" => name = file_basename(&value)?,
+                // Urls end up in proxy and browser logs, which do not mask them
+                "passphrase" => return Err(Error::Parse("passphrase can not be passed in url".to_string())),
+                _ => req.set_field(&field, &value)?,
+            }
+        }
+        req.file = Some(ApiFile { name, mime: MIME_OCTET_STREAM.to_string(), content: body });
+        Ok(req)
+    }
+}
+
+/// Read request body, but not more than `limit` bytes. Body with declared length over the limit
+/// is rejected before anything is read
+fn read_body(r: &mut Request, limit: usize) -> Result<Vec<u8>> {
+    if r.body_length().is_some_and(|len| len > limit) {
+        return Err(Error::PayloadTooLarge);
+    }
+    let mut body = Vec::new();
+    r.as_reader().take(limit as u64 + 1).read_to_end(&mut body).map_err(
+        get_reporter(MODULE, "read_body", Error::parse)
+    )?;
+    // Chunked body has no declared length, so it is cut here
+    if body.len() > limit {
+        return Err(Error::PayloadTooLarge);
+    }
+    Ok(body)
+}
+
+/// File name without directories, browsers send full path sometimes
+fn file_basename(name: &str) -> Result<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    if name.chars().count() > MAX_FILE_FIELD_LENGTH {
+        return Err(Error::Parse("file name is too long".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// MIME type of uploaded file, if it is safe to put it into response header
+fn valid_mime(mime: Option<&str>) -> Result<String> {
+    match mime {
+        Some(m) if m.chars().count() > MAX_FILE_FIELD_LENGTH => Err(Error::Parse("MIME type is too long".to_string())),
+        Some(m) if m.contains('/') && m.bytes().all(|b| b.is_ascii_graphic() || b == b' ') => Ok(m.to_string()),
+        _ => Ok(MIME_OCTET_STREAM.to_string()),
+    }
+}
+
+/// Optional body of POST /get request, sent either as JSON or as html form
+#[derive(Default, Deserialize)]
+pub struct ApiGetRequest {
+    #[serde(default)]
+    passphrase: Option<String>,
+}
+
+impl ApiGetRequest {
+    pub fn get_passphrase(&self) -> Option<&String> { self.passphrase.as_ref().filter(|p| !p.is_empty()) }
+
+    pub fn parse_from(r: &mut Request, cfg: &config::Server) -> Result<ApiGetRequest> {
+        let is_form = r.headers().iter().any(
+            |h| h.field.equiv("Content-Type") && h.value.as_str().starts_with("application/x-www-form-urlencoded")
+        );
+        let body = String::from_utf8(read_body(r, cfg.max_body_bytes)?).map_err(
+            get_reporter(MODULE, "ApiGetRequest::parse_from", Error::parse)
+        )?;
+
+        if body.is_empty() {
+            return Ok(ApiGetRequest::default());
+        }
+        // Like /add, accept JSON whatever content type is, since `curl -d` sends it as a form
+        match serde_json::from_str(&body) {
+            Ok(req) => Ok(req),
+            Err(_) if is_form => {
+                let passphrase = parse_query(&body).into_iter()
+                    .find(|(name, _)| name == "passphrase")
+                    .map(|(_, value)| value);
+                Ok(ApiGetRequest { passphrase })
+            },
+            Err(e) => Err(get_reporter(MODULE, "ApiGetRequest::parse_from", Error::parse)(e)),
+        }
     }
 }
 
@@ -41,11 +295,44 @@ pub struct ApiResponse {
 
     #[serde(skip_serializing_if = "is_zero")]
     expired: i64,
+
+    #[serde(skip_serializing_if = "is_zero_u32")]
+    clicks: u32,
+
+    #[serde(skip_serializing_if = "is_zero_u64")]
+    lifetime: u64,
+
+    #[serde(skip_serializing_if = "is_zero")]
+    not_before: i64,
+
+    #[serde(skip_serializing_if = "String::is_empty")]
+    token: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ApiError>,
+
+    #[serde(skip_serializing_if = "String::is_empty")]
+    request_id: String,
+}
+
+/// Machine readable description of the error, `status` keeps the human readable one
+#[derive(Serialize)]
+pub struct ApiError {
+    code: &'static str,
+}
+
+impl Default for ApiResponse {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ApiResponse {
     pub fn new() -> Self {
-        Self { msg: String::new(), status: "OK".to_string(), created: now(), expired: 0 }
+        Self {
+            msg: String::new(), status: "OK".to_string(), created: now(), expired: 0, clicks: 0, lifetime: 0, not_before: 0,
+            token: String::new(), error: None, request_id: String::new(),
+        }
     }
 
     pub fn set_message(&mut self, msg: String) {
@@ -64,6 +351,30 @@ impl ApiResponse {
         self.expired = expired;
     }
 
+    pub fn set_clicks(&mut self, clicks: u32) {
+        self.clicks = clicks;
+    }
+
+    pub fn set_lifetime(&mut self, lifetime: u64) {
+        self.lifetime = lifetime;
+    }
+
+    pub fn set_not_before(&mut self, not_before: i64) {
+        self.not_before = not_before;
+    }
+
+    pub fn set_token(&mut self, token: String) {
+        self.token = token;
+    }
+
+    pub fn set_error_code(&mut self, code: &'static str) {
+        self.error = Some(ApiError { code });
+    }
+
+    pub fn set_request_id(&mut self, request_id: String) {
+        self.request_id = request_id;
+    }
+
     pub fn message(&self) -> &String {
         &self.msg
     }
@@ -80,8 +391,15 @@ impl ApiResponse {
         self.expired
     }
 
+    pub fn clicks(&self) -> u32 {
+        self.clicks
+    }
+
     pub fn hide_sensitive(&mut self) {
         self.set_created(0);
         self.set_expired(0);
+        self.set_clicks(0);
+        self.set_lifetime(0);
+        self.set_token(String::new());
     }
 }
@@ -0,0 +1,55 @@
+use tiny_http::Request;
+
+use crate::config;
+use crate::crypto;
+use crate::error::Error;
+use crate::utils::Result;
+
+
+const MODULE: &str = "AUTH";
+
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+
+
+/// Check API key from `Authorization: Bearer <key>` header and return id of the matched key.
+/// Empty id is returned if authentication is disabled
+pub fn authenticate(cfg: &config::Auth, r: &Request) -> Result<String> {
+    if !cfg.enabled {
+        return Ok(String::new());
+    }
+
+    let token = r.headers().iter()
+        .find(|h| h.field.equiv(AUTHORIZATION_HEADER))
+        .and_then(|h| strip_bearer(h.value.as_str()))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or(Error::Auth)?;
+
+    // Keys are compared by their hashes, so config may contain hashes only
+    let hash = crypto::sha256_hex(token);
+    match cfg.keys.iter().find(|k| key_hash(k) == hash) {
+        Some(k) => Ok(k.id.clone()),
+        None => {
+            warn!("[{}] Request with unknown API key", MODULE);
+            Err(Error::Auth)
+        }
+    }
+}
+
+/// Authentication scheme is case-insensitive (RFC 9110), so `bearer` is accepted as well
+fn strip_bearer(value: &str) -> Option<&str> {
+    let prefix = value.get(..BEARER_PREFIX.len())?;
+    if !prefix.eq_ignore_ascii_case(BEARER_PREFIX) {
+        return None;
+    }
+    value.get(BEARER_PREFIX.len()..)
+}
+
+fn key_hash(k: &config::ApiKey) -> String {
+    if k.hash.is_empty() {
+        crypto::sha256_hex(&k.key)
+    } else {
+        k.hash.to_lowercase()
+    }
+}
@@ -2,7 +2,8 @@ use config;
 use serde::Deserialize;
 use simplelog::LevelFilter;
 
-use crate::utils::Result;
+use crate::error::Error;
+use crate::utils::{Result, ResultV};
 
 
 #[derive(serde_derive::Deserialize)]
@@ -11,12 +12,21 @@ pub struct Database {
     pub url: String,
 }
 
+#[derive(Clone, serde_derive::Deserialize)]
+pub struct Tls {
+    pub cert: String,
+    pub key: String,
+}
+
 #[derive(serde_derive::Deserialize)]
 pub struct Server {
     pub host: String,
     pub port: u32,
     pub workers: usize,
     pub address: String,
+    pub max_body_bytes: usize,
+    pub max_secret_bytes: usize,
+    pub tls: Option<Tls>,
 }
 
 #[derive(serde_derive::Deserialize)]
@@ -27,15 +37,148 @@ pub struct Log {
     pub file: String,
 }
 
+#[derive(serde_derive::Deserialize)]
+pub struct Sweeper {
+    pub enabled: bool,
+    pub interval: u64,
+}
+
+#[derive(serde_derive::Deserialize)]
+pub struct Security {
+    pub pepper: String,
+    pub max_passphrase_attempts: u32,
+}
+
+impl Security {
+    /// Link ids are hashed with the pepper, so it can not be empty. Zero attempts would lock every passphrase out
+    pub fn check(&self) -> ResultV {
+        if self.pepper.is_empty() {
+            return Err(Error::Config("`security.pepper` has to be set".to_string()));
+        }
+        if self.max_passphrase_attempts == 0 {
+            return Err(Error::Config("`security.max_passphrase_attempts` has to be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde_derive::Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    #[serde(default)]
+    pub key: String,
+    #[serde(default)]
+    pub hash: String,
+}
+
+#[derive(serde_derive::Deserialize)]
+pub struct Auth {
+    pub enabled: bool,
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(serde_derive::Deserialize, Clone)]
+pub struct RateLimit {
+    pub enabled: bool,
+    pub burst: u32,
+    pub rate: f64,
+    pub max_not_found: u32,
+    pub ban_time: u64,
+}
+
+impl RateLimit {
+    /// Bucket has to refill, otherwise clients would never become idle and be forgotten
+    pub fn check(&self) -> ResultV {
+        if self.enabled && (!self.rate.is_finite() || self.rate <= 0.0) {
+            return Err(Error::Config("`rate_limit.rate` has to be a positive number".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// What to do with requested lifetime or clicks which are out of policy limits
+#[derive(serde_derive::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyMode {
+    Clamp,
+    Reject,
+}
+
+#[derive(serde_derive::Deserialize)]
+pub struct Policy {
+    pub mode: PolicyMode,
+    pub min_lifetime: u64,
+    pub max_lifetime: u64,
+    pub default_lifetime: u64,
+    pub max_clicks: u32,
+    pub default_max_clicks: u32,
+}
+
+impl Policy {
+    /// Limits have to be consistent and default values have to be within them
+    pub fn check(&self) -> ResultV {
+        if self.min_lifetime == 0 || self.min_lifetime > self.max_lifetime {
+            return Err(Error::Config("`policy.min_lifetime` has to be in [1, max_lifetime]".to_string()));
+        }
+        if !(self.min_lifetime..=self.max_lifetime).contains(&self.default_lifetime) {
+            return Err(Error::Config("`policy.default_lifetime` has to be in [min_lifetime, max_lifetime]".to_string()));
+        }
+        if !(1..=self.max_clicks).contains(&self.default_max_clicks) {
+            return Err(Error::Config("`policy.default_max_clicks` has to be in [1, max_clicks]".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Characters which can be used in link ids, they are not escaped in urls
+const ID_SAFE_CHARS: &str = "-._~";
+
+#[derive(serde_derive::Deserialize)]
+pub struct Ids {
+    pub length: u32,
+    pub alphabet: String,
+}
+
+impl Ids {
+    /// Alphabet has to be url safe and without repeats, so every character is equally likely
+    pub fn check(&self) -> ResultV {
+        if !(8..=256).contains(&self.length) {
+            return Err(Error::Config("`ids.length` has to be in [8, 256]".to_string()));
+        }
+        if !self.alphabet.chars().all(|c| c.is_ascii_alphanumeric() || ID_SAFE_CHARS.contains(c)) {
+            return Err(Error::Config(format!("`ids.alphabet` may contain only ASCII letters, digits and `{}`", ID_SAFE_CHARS)));
+        }
+        let mut chars: Vec<char> = self.alphabet.chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        if chars.len() != self.alphabet.len() || chars.len() < 2 {
+            return Err(Error::Config("`ids.alphabet` has to contain at least 2 distinct characters without repeats".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Number of random bits in one id
+    pub fn entropy_bits(&self) -> f64 {
+        (self.alphabet.len() as f64).log2() * self.length as f64
+    }
+}
+
 #[derive(serde_derive::Deserialize)]
 pub struct Config {
     pub database: Database,
     pub server: Server,
     pub log: Log,
+    pub sweeper: Sweeper,
+    pub security: Security,
+    pub auth: Auth,
+    pub rate_limit: RateLimit,
+    pub policy: Policy,
+    pub ids: Ids,
 }
 
 impl Config {
-    pub fn load(path: &String) -> Self {
+    pub fn load(path: &str) -> Self {
         config::Config::builder()
             .add_source(config::File::with_name(path))
             .set_default("database.kind",   String::from("memory")                  ).unwrap()
@@ -44,9 +187,29 @@ impl Config {
             .set_default("server.port",     String::from("8080")                    ).unwrap()
             .set_default("server.workers",  32                                      ).unwrap()
             .set_default("server.address",  String::from("http://127.0.0.1:8080")   ).unwrap()
+            .set_default("server.max_body_bytes", 10485760                      ).unwrap()
+            .set_default("server.max_secret_bytes", 8388608                     ).unwrap()
             .set_default("log.kind",        String::from("console")                 ).unwrap()
             .set_default("log.file",        String::from("onetimer.log")            ).unwrap()
             .set_default("log.level",       String::from("info")                    ).unwrap()
+            .set_default("sweeper.enabled", true                                    ).unwrap()
+            .set_default("sweeper.interval", 60                                     ).unwrap()
+            .set_default("security.pepper", String::new()                           ).unwrap()
+            .set_default("security.max_passphrase_attempts", 5                      ).unwrap()
+            .set_default("auth.enabled",    false                                   ).unwrap()
+            .set_default("rate_limit.enabled", true                                 ).unwrap()
+            .set_default("rate_limit.burst", 30                                     ).unwrap()
+            .set_default("rate_limit.rate", 5.0                                     ).unwrap()
+            .set_default("rate_limit.max_not_found", 20                             ).unwrap()
+            .set_default("rate_limit.ban_time", 300                                 ).unwrap()
+            .set_default("policy.mode",     String::from("clamp")                   ).unwrap()
+            .set_default("policy.min_lifetime", 1                                   ).unwrap()
+            .set_default("policy.max_lifetime", 2592000                             ).unwrap()
+            .set_default("policy.default_lifetime", 604800                          ).unwrap()
+            .set_default("policy.max_clicks", 100                                   ).unwrap()
+            .set_default("policy.default_max_clicks", 1                             ).unwrap()
+            .set_default("ids.length",      64                                      ).unwrap()
+            .set_default("ids.alphabet",    String::from("0123456789abcdef")        ).unwrap()
             .build().unwrap()
             .try_deserialize().unwrap()
     }
@@ -61,7 +224,7 @@ impl Config {
             "warn"  => Ok(LevelFilter::Warn),
             _       => {
                 eprintln!("Unknown log level: {}", level_str);
-                Err("unknown log level")
+                Err(Error::Config(format!("unknown log level `{}`", level_str)))
             }
         }
     }
@@ -71,6 +234,6 @@ fn deserialize_log_level<'de, D>(deserializer: D) -> std::result::Result<LevelFi
 where D: serde::Deserializer<'de> {
     use serde::de::Error;
     let buf = String::deserialize(deserializer)?;
-    Config::get_log_level(buf).map_err(|e| Error::custom(e))
+    Config::get_log_level(buf).map_err(Error::custom)
 }
 
@@ -3,6 +3,8 @@ use std::sync::{Arc, Mutex, MutexGuard};
 use crate::api::ApiResponse;
 use crate::config::Config;
 use crate::db::DB;
+use crate::error::Error;
+use crate::limiter::RateLimiter;
 use crate::logger::get_reporter;
 use crate::utils::{generate_hex_id, time_us, Result};
 
@@ -15,17 +17,19 @@ pub struct Context {
     pub finish_time_us: u128,
     pub cfg: Arc<Config>,
     pub db: Arc<Mutex<DB>>,
+    pub limiter: Arc<RateLimiter>,
     pub resp: ApiResponse,
 }
 
 impl Context {
-    pub fn new(db: Arc<Mutex<DB>>, cfg: Arc::<Config>) -> Context {
+    pub fn new(db: Arc<Mutex<DB>>, cfg: Arc::<Config>, limiter: Arc<RateLimiter>) -> Context {
         Context {
             qid: generate_hex_id(8),
             start_time_us: time_us(),
             finish_time_us: 0,
             cfg,
             db,
+            limiter,
             resp: ApiResponse::new(),
         }
     }
@@ -38,7 +42,7 @@ impl Context {
         ((self.finish_time_us - self.start_time_us) as f32)/1000.0
     }
 
-    pub fn db(&mut self) -> Result<MutexGuard<DB>> {
-        self.db.lock().map_err(get_reporter(MODULE, "Context", "context error"))
+    pub fn db(&mut self) -> Result<MutexGuard<'_, DB>> {
+        self.db.lock().map_err(get_reporter(MODULE, "Context", |_| Error::Internal("context error")))
     }
 }
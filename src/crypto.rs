@@ -0,0 +1,81 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::logger::get_reporter;
+use crate::utils::Result;
+
+
+const MODULE: &str = "CRYPTO";
+
+const NONCE_LENGTH: usize = 24;
+
+pub const ENCRYPT_ERROR: &str = "encrypt error";
+pub const DECRYPT_ERROR: &str = "decrypt error";
+pub const HASH_ERROR: &str = "hash error";
+
+
+fn cipher(key: &str) -> XChaCha20Poly1305 {
+    let key = Sha256::digest(key.as_bytes());
+    XChaCha20Poly1305::new(&key)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// HMAC-SHA256 of the value keyed with server pepper, hex encoded
+pub fn keyed_hash(pepper: &str, value: &str) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(pepper.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(value.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+/// SHA-256 of the value, hex encoded
+pub fn sha256_hex(value: &str) -> String {
+    hex(&Sha256::digest(value.as_bytes()))
+}
+
+/// Argon2 hash of the passphrase in PHC string format
+pub fn hash_passphrase(passphrase: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default().hash_password(passphrase.as_bytes(), &salt).map(|h| h.to_string()).map_err(
+        get_reporter(MODULE, "Hash", |_| Error::Crypto(HASH_ERROR))
+    )
+}
+
+/// Check passphrase against its hash produced by `hash_passphrase`
+pub fn verify_passphrase(passphrase: &str, hash: &str) -> Result<bool> {
+    let parsed = PasswordHash::new(hash).map_err(get_reporter(MODULE, "Verify", |_| Error::Crypto(HASH_ERROR)))?;
+    Ok(Argon2::default().verify_password(passphrase.as_bytes(), &parsed).is_ok())
+}
+
+/// Encrypt data with the key taken from the link. Link id is used as associated data,
+/// so ciphertext can not be moved to another record. Result is nonce + ciphertext
+pub fn encrypt(key: &str, id: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(key).encrypt(&nonce, Payload{msg: data, aad: id.as_bytes()}).map_err(
+        get_reporter(MODULE, "Encrypt", |_| Error::Crypto(ENCRYPT_ERROR))
+    )?;
+
+    let mut out = nonce.to_vec();
+    out.extend(ciphertext);
+    Ok(out)
+}
+
+/// Decrypt data produced by `encrypt`, fails if key or id do not match
+pub fn decrypt(key: &str, id: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LENGTH {
+        return Err(Error::Crypto(DECRYPT_ERROR));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+
+    // Wrong key is expected for guessed links, so there is nothing to report
+    cipher(key).decrypt(XNonce::from_slice(nonce), Payload{msg: ciphertext, aad: id.as_bytes()}).map_err(
+        |_| Error::Crypto(DECRYPT_ERROR)
+    )
+}
@@ -1,16 +1,25 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::fs::OpenOptions;
+use std::io::{ErrorKind, Seek, SeekFrom};
 
-use mysql::{params, prelude::Queryable};
-use postgres::NoTls;
+use mysql::{params, prelude::Queryable, TxOpts};
+use postgres::error::SqlState;
+use postgres::{GenericClient, NoTls};
 use r2d2_postgres::PostgresConnectionManager;
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition};
 use serde::{Deserialize, Serialize};
 use sqlite::{ReadableWithIndex, State, Statement, Value};
 
 use crate::api::ApiAddRequest;
+use crate::config::Config;
+use crate::crypto;
+use crate::error::{BoxError, Error};
 use crate::logger::get_reporter;
-use crate::utils::{now, ErrorStr, Result, ResultV};
+use crate::metrics::{Event, METRICS};
+use crate::migrations;
+use crate::utils::{generate_hex_id, now, Result, ResultV};
 
 
 const MODULE: &str = "DB";
@@ -20,99 +29,232 @@ const DB_MEMORY: &str = "memory";
 const DB_FILE: &str = "file";
 const DB_MYSQL: &str = "mysql";
 const DB_PGSQL: &str = "postgresql";
+const DB_REDB: &str = "redb";
+const DB_REDIS: &str = "redis";
+
+const TABLE_EXISTS_SQLITE_QUERY: &str = "SELECT COUNT(*) AS count FROM sqlite_master WHERE type = 'table' AND name = 'msg'";
+const TABLE_EXISTS_MYSQL_QUERY: &str = "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = 'msg'";
+const TABLE_EXISTS_PGSQL_QUERY: &str = "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = 'msg'";
+const LOCK_MIGRATIONS_MYSQL_QUERY: &str = "SELECT GET_LOCK('onetimer_migrations', :timeout)";
+const UNLOCK_MIGRATIONS_MYSQL_QUERY: &str = "SELECT RELEASE_LOCK('onetimer_migrations')";
+const LOCK_MIGRATIONS_PGSQL_QUERY: &str = "SELECT pg_advisory_xact_lock($1)";
+
+/// Instances started at the same time wait for each other, so every migration is applied once
+const MIGRATION_LOCK_TIMEOUT_S: u64 = 60;
+/// Key of PostgreSQL advisory lock held while migrating
+const MIGRATION_LOCK_PGSQL_KEY: i64 = 0x6f6e6574696d6572;
+/// Result code of SQLite for constraint violations. On insert it can come from primary key only,
+/// since every NOT NULL column is bound
+const SQLITE_CONSTRAINT: isize = 19;
+/// MySQL error code for duplicate key (ER_DUP_ENTRY)
+const MYSQL_DUPLICATE_ENTRY: u16 = 1062;
 
-const PREPARE_DB_SQL_QUERY: &str = "CREATE TABLE IF NOT EXISTS msg (id TEXT NOT NULL, data TEXT, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL);";
 const SELECT_BY_ID_SQL_QUERY: &str = "SELECT * FROM msg WHERE id = :id LIMIT 1";
 const DELETE_BY_ID_SQL_QUERY: &str = "DELETE FROM msg WHERE id = :id";
 const UPDATE_BY_ID_SQL_QUERY: &str = "UPDATE msg SET max_clicks = :max_clicks WHERE id = :id";
-const INSERT_SQL_QUERY: &str = "INSERT INTO msg (id, data, max_clicks, created, lifetime) VALUES (:id, :data, :max_clicks, :created, :lifetime)";
+const CONSUME_BY_ID_SQL_QUERY: &str = "UPDATE msg SET max_clicks = max_clicks - 1 WHERE id = :id AND max_clicks > 0 RETURNING *";
+const DELETE_CONSUMED_BY_ID_SQL_QUERY: &str = "DELETE FROM msg WHERE id = :id AND max_clicks <= 0";
+const SELECT_FOR_UPDATE_BY_ID_SQL_QUERY: &str = "SELECT * FROM msg WHERE id = :id LIMIT 1 FOR UPDATE";
+const FAIL_ATTEMPT_BY_ID_SQL_QUERY: &str = "UPDATE msg SET attempts = attempts + 1 WHERE id = :id RETURNING *";
+const FAIL_ATTEMPT_BY_ID_MYSQL_QUERY: &str = "UPDATE msg SET attempts = attempts + 1 WHERE id = :id";
+const PURGE_EXPIRED_SQL_QUERY: &str = "DELETE FROM msg WHERE expires_at < :now";
+const PURGE_EXHAUSTED_SQL_QUERY: &str = "DELETE FROM msg WHERE max_clicks <= 0";
+const PING_SQL_QUERY: &str = "SELECT 1";
+const CREATE_SCHEMA_VERSION_SQL_QUERY: &str = "CREATE TABLE IF NOT EXISTS schema_version (version BIGINT NOT NULL, applied BIGINT NOT NULL)";
+const SELECT_SCHEMA_VERSION_SQL_QUERY: &str = "SELECT COALESCE(MAX(version), 0) AS version FROM schema_version";
+const INSERT_SCHEMA_VERSION_SQL_QUERY: &str = "INSERT INTO schema_version (version, applied) VALUES (:version, :applied)";
+const COUNT_SQL_QUERY: &str = "SELECT COUNT(*) AS count FROM msg WHERE expires_at >= :now AND max_clicks > 0";
+const INSERT_SQL_QUERY: &str = "INSERT INTO msg (id, data, max_clicks, created, lifetime, expires_at, token, passphrase, attempts, owner, filename, mime, not_before) VALUES (:id, :data, :max_clicks, :created, :lifetime, :expires_at, :token, :passphrase, :attempts, :owner, :filename, :mime, :not_before)";
 
 const DELETE_BY_ID_PGSQL_QUERY: &str = "DELETE FROM msg WHERE id = $1";
-const INSERT_PGSQL_QUERY: &str = "INSERT INTO msg (id, data, max_clicks, created, lifetime) VALUES ($1, $2, $3, $4, $5)";
+const INSERT_SCHEMA_VERSION_PGSQL_QUERY: &str = "INSERT INTO schema_version (version, applied) VALUES ($1, $2)";
+const INSERT_PGSQL_QUERY: &str = "INSERT INTO msg (id, data, max_clicks, created, lifetime, expires_at, token, passphrase, attempts, owner, filename, mime, not_before) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)";
 const SELECT_BY_ID_PGSQL_QUERY: &str = "SELECT * FROM msg WHERE id = $1 LIMIT 1";
-const UPDATE_BY_ID_PGSQL_QUERY: &str = "UPDATE msg SET max_clicks = $1 WHERE id = $2";
+const CONSUME_BY_ID_PGSQL_QUERY: &str = "UPDATE msg SET max_clicks = max_clicks - 1 WHERE id = $1 AND max_clicks > 0 RETURNING *";
+const DELETE_CONSUMED_BY_ID_PGSQL_QUERY: &str = "DELETE FROM msg WHERE id = $1 AND max_clicks <= 0";
+const FAIL_ATTEMPT_BY_ID_PGSQL_QUERY: &str = "UPDATE msg SET attempts = attempts + 1 WHERE id = $1 RETURNING *";
+const PURGE_EXPIRED_PGSQL_QUERY: &str = "DELETE FROM msg WHERE expires_at < $1";
+const COUNT_PGSQL_QUERY: &str = "SELECT COUNT(*) AS count FROM msg WHERE expires_at >= $1 AND max_clicks > 0";
 
-pub const SQLITE_ERROR: &str = "sqlite error";
-pub const MYSQL_ERROR: &str = "mysql error";
-pub const MEMORY_ERROR: &str = "memory error";
-pub const IO_ERROR: &str = "io error";
-pub const PGSQL_ERROR: &str = "pgsql error";
+const REDB_RECORDS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("msg");
+/// Secondary index of records by expiry time, so expired records are found without full scan
+const REDB_EXPIRY_TABLE: TableDefinition<(i64, &str), ()> = TableDefinition::new("msg_expiry");
 
-pub const NOT_FOUND_ERROR: &str = "not found";
-pub const UNKNOWN_DB_TYPE_ERROR: &str = "unknown db kind";
-pub const ALREADY_EXISTS_ERROR: &str = "already exists";
-pub const DO_NOT_EXISTS_ERROR: &str = "do not exists";
-pub const DELETE_ERROR: &str = "delete error";
+type RedbRecords<'txn> = redb::Table<'txn, &'static str, &'static [u8]>;
+type RedbExpiry<'txn> = redb::Table<'txn, (i64, &'static str), ()>;
 
+const REDIS_KEY_PREFIX: &str = "onetimer:msg:";
+/// Scripts keep TTL of the key and run atomically on redis side. First line names the script for RESP stand-ins
+const REDIS_CONSUME_SCRIPT: &str = r#"-- onetimer:consume
+local value = redis.call('GET', KEYS[1])
+if not value then return false end
+local r = cjson.decode(value)
+if r.max_clicks <= 0 then return false end
+r.max_clicks = r.max_clicks - 1
+value = cjson.encode(r)
+if r.max_clicks == 0 then
+    redis.call('DEL', KEYS[1])
+else
+    redis.call('SET', KEYS[1], value, 'KEEPTTL')
+end
+return value
+"#;
+const REDIS_FAIL_ATTEMPT_SCRIPT: &str = r#"-- onetimer:fail_attempt
+local value = redis.call('GET', KEYS[1])
+if not value then return false end
+local r = cjson.decode(value)
+r.attempts = r.attempts + 1
+redis.call('SET', KEYS[1], cjson.encode(r), 'KEEPTTL')
+return r.attempts
+"#;
 
 pub trait DbEngine: Sync + Send {
-    /// Insert data from ApiAddRequest with given id to database
-    fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV;
+    /// Insert record to database
+    fn insert(&mut self, r: &Record) -> ResultV;
 
     /// Get record from database by id
-    fn get(&mut self, id: &String) -> Result<Record>;
+    fn get(&mut self, id: &str) -> Result<Record>;
 
     /// Delete record from database by id
-    fn delete(&mut self, id: &String)-> ResultV;
+    fn delete(&mut self, id: &str)-> ResultV;
 
     /// Update record in database
-    fn update(&mut self, r: Record)-> ResultV;
+    fn consume(&mut self, id: &str) -> Result<Record>;
+
+    /// Atomically increment failed passphrase attempts counter of the record and return its new value
+    fn fail_attempt(&mut self, id: &str) -> Result<u32>;
+
+    /// Delete all expired and exhausted records, return numbers of deleted records
+    fn purge(&mut self) -> Result<Purged>;
+
+    /// Count records which are neither expired nor exhausted. Engines which can not count them cheaply
+    /// return `Error::NotImplemented`
+    fn count(&mut self) -> Result<usize>;
+
+    /// Check that database is reachable
+    fn ping(&mut self) -> ResultV;
 
     /// Create new instance of engine
-    fn new(path: &String) -> Result<Self> where Self: Sized;
+    fn new(path: &str) -> Result<Self> where Self: Sized;
 
     /// Create new instance of engine in the heap
-    fn new_boxed(path: &String) -> Result<Box<Self>> where Self: Sized {
-        Self::new(path).map(|e| Box::new(e))
+    fn new_boxed(path: &str) -> Result<Box<Self>> where Self: Sized {
+        Self::new(path).map(Box::new)
     }
 
-    /// Prepare engine (create tables if needed)
+    /// Prepare engine (create tables if needed). SQL engines bring database schema up to date here
     fn prepare(&mut self) -> ResultV;
 }
 
 trait Reportable {
-    fn report(e: impl Display) -> ErrorStr;
+    /// Log backend error and wrap it into `Error::Backend`
+    fn report(e: impl Display + Into<BoxError>) -> Error;
 }
 
 pub struct DB {
     kind: String,
+    pepper: String,
+    max_attempts: u32,
     engine: Box<dyn DbEngine>,
 }
 
 impl DB {
-    fn new_engine(kind: &String, path: &String) -> Result<Box<dyn DbEngine>> {
-        match kind.as_str() {
+    fn new_engine(kind: &str, path: &str) -> Result<Box<dyn DbEngine>> {
+        match kind {
             DB_SQLITE => Ok(SqliteEngine::new_boxed(path)?),
             DB_MEMORY => Ok(MemoryEngine::new_boxed(path)?),
             DB_FILE   => Ok(FileEngine::new_boxed(path)?),
             DB_MYSQL  => Ok(MysqlEngine::new_boxed(path)?),
             DB_PGSQL  => Ok(PostgresqlEngine::new_boxed(path)?),
+            DB_REDB   => Ok(RedbEngine::new_boxed(path)?),
+            DB_REDIS  => Ok(RedisEngine::new_boxed(path)?),
             _ => {
                 error!("[{}] Unknown database kind: {}", MODULE, kind);
-                Err(UNKNOWN_DB_TYPE_ERROR)
+                Err(Error::Config(format!("unknown database kind `{}`", kind)))
             }
         }
     }
-    pub fn new(typ: &String, path: &String) -> Result<DB> {
-        Ok(DB{kind: typ.clone(), engine: Self::new_engine(typ, path)?})
+    pub fn new(cfg: &Config) -> Result<DB> {
+        Ok(DB{
+            kind: cfg.database.kind.clone(),
+            pepper: cfg.security.pepper.clone(),
+            max_attempts: cfg.security.max_passphrase_attempts,
+            engine: Self::new_engine(&cfg.database.kind, &cfg.database.url)?,
+        })
+    }
+    /// Records are stored by keyed hash of link id, so database dump does not contain working links
+    fn hash_id(&self, id: &str) -> String {
+        crypto::keyed_hash(&self.pepper, id)
+    }
+    /// Passphrase has to be already hashed with `crypto::hash_passphrase`, or empty
+    pub fn insert(&mut self, id: &str, data: Vec<u8>, token: &str, passphrase: &str, owner: &str, msg: &ApiAddRequest) -> ResultV {
+        let r = Record::new(&self.hash_id(id), data, &self.hash_id(token), passphrase.to_string(), owner, msg);
+        self.engine.insert(&r)
     }
-    pub fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV {
-        self.engine.insert(id, msg)
+    /// Find the record and decrypt its data without consuming a click, so that wrong key or passphrase
+    /// does not waste it. Passphrase is checked by caller with `Found::check_passphrase`
+    pub fn lookup(&mut self, id: &str, key: &str) -> Result<Found> {
+        let r = self.engine.get(&self.hash_id(id))?;
+        let data = crypto::decrypt(key, id, &r.data).map_err(|_| Error::NotFound)?;
+        if r.pending() {
+            return Err(Error::NotYetAvailable(r.not_before));
+        }
+        Ok(Found { record: r, data })
     }
-    pub fn select(&mut self, id: &String) -> Result<String> {
-        let mut r = self.engine.get(id)?;
-        let expired = r.expired();
-        let data = r.data.clone();
-        if r.max_clicks == 1 || expired {
-            self.engine.delete(id)?;
-            if expired {
-                return Err(NOT_FOUND_ERROR);
+    /// Consume a click of the record found by `lookup`
+    pub fn consume(&mut self, found: Found) -> Result<Secret> {
+        let r = self.engine.consume(&found.record.id)?;
+        if r.expired() {
+            // Exhausted records are already deleted by the engine
+            if r.max_clicks > 0 {
+                self.engine.delete(&r.id)?;
             }
-        } else {
-            r.max_clicks -= 1;
-            self.engine.update(r)?;
+            METRICS.count(Event::Expired);
+            return Err(Error::NotFound);
+        }
+        Ok(Secret { data: found.data, filename: r.filename, mime: r.mime })
+    }
+    /// Count wrong passphrase attempt, the record is deleted after too many of them
+    pub fn fail_passphrase(&mut self, found: &Found) -> Error {
+        let attempts = match self.engine.fail_attempt(&found.record.id) {
+            Ok(attempts) => attempts,
+            Err(e) => return e,
+        };
+        if attempts >= self.max_attempts {
+            warn!("[{}] Too many wrong passphrase attempts, delete the record", MODULE);
+            return self.engine.delete(&found.record.id).err().unwrap_or(Error::NotFound);
+        }
+        Error::WrongPassphrase
+    }
+    /// Delete record on behalf of its owner, who proves ownership with management token.
+    /// Wrong token is reported as missing record, so ids can not be probed this way
+    pub fn revoke(&mut self, id: &str, token: &str) -> ResultV {
+        let hid = self.hash_id(id);
+        let r = self.engine.get(&hid)?;
+        if r.token.is_empty() || r.token != self.hash_id(token) {
+            return Err(Error::NotFound);
+        }
+        self.engine.delete(&hid)
+    }
+    /// Get record metadata without consuming a click
+    pub fn info(&mut self, id: &str) -> Result<Record> {
+        let r = self.engine.get(&self.hash_id(id))?;
+        if r.expired() || r.max_clicks == 0 {
+            return Err(Error::NotFound);
         }
-        return Ok(data);
+        Ok(r)
+    }
+    pub fn purge(&mut self) -> Result<Purged> {
+        let purged = self.engine.purge()?;
+        METRICS.count_n(Event::Expired, purged.expired as u64);
+        METRICS.count_n(Event::Exhausted, purged.exhausted as u64);
+        Ok(purged)
+    }
+    pub fn count(&mut self) -> Result<usize> {
+        self.engine.count()
+    }
+    pub fn ping(&mut self) -> ResultV {
+        self.engine.ping()
     }
     pub fn prepare(&mut self) -> ResultV {
         let connected = self.engine.prepare();
@@ -142,63 +284,100 @@ struct MysqlEngine {
 struct PostgresqlEngine {
     pool: r2d2::Pool<PostgresConnectionManager<NoTls>>,
 }
+struct RedbEngine {
+    db: redb::Database,
+}
+struct RedisEngine {
+    pool: r2d2::Pool<redis::Client>,
+}
 
 
 impl Reportable for SqliteEngine {
-    fn report(e: impl Display) -> ErrorStr {
-        get_reporter(MODULE, "SQLite", SQLITE_ERROR)(e)
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "SQLite", |e| Error::backend(DB_SQLITE, e))(e)
     }
 }
 impl Reportable for MemoryEngine {
-    fn report(e: impl Display) -> ErrorStr {
-        get_reporter(MODULE, "Memory", MEMORY_ERROR)(e)
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "Memory", |e| Error::backend(DB_MEMORY, e))(e)
     }
 }
 impl Reportable for FileEngine {
-    fn report(e: impl Display) -> ErrorStr {
-        get_reporter(MODULE, "IO", IO_ERROR)(e)
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "IO", |e| Error::backend(DB_FILE, e))(e)
     }
 }
 impl Reportable for MysqlEngine {
-    fn report(e: impl Display) -> ErrorStr {
-        get_reporter(MODULE, "MySQL", MYSQL_ERROR)(e)
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "MySQL", |e| Error::backend(DB_MYSQL, e))(e)
     }
 }
 impl Reportable for PostgresqlEngine {
-    fn report(e: impl Display) -> ErrorStr {
-        get_reporter(MODULE, "PostgreSQL", PGSQL_ERROR)(e)
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "PostgreSQL", |e| Error::backend(DB_PGSQL, e))(e)
+    }
+}
+impl Reportable for RedbEngine {
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "redb", |e| Error::backend(DB_REDB, e))(e)
+    }
+}
+impl Reportable for RedisEngine {
+    fn report(e: impl Display + Into<BoxError>) -> Error {
+        get_reporter(MODULE, "Redis", |e| Error::backend(DB_REDIS, e))(e)
     }
 }
 
 
 impl DbEngine for MemoryEngine {
-    fn new(_path: &String) -> Result<Self> {
+    fn new(_path: &str) -> Result<Self> {
         Ok(MemoryEngine { map: HashMap::new() })
     }
-    fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV {
-        match self.map.insert(id.clone(), Record::new(id, msg)) {
-            None => Ok(()),
-            Some(_) => {
-                // TODO: add logging here
-                Err(ALREADY_EXISTS_ERROR)
-            }
+    fn insert(&mut self, r: &Record) -> ResultV {
+        match self.map.entry(r.id.clone()) {
+            Entry::Vacant(e) => {
+                e.insert(r.clone());
+                Ok(())
+            },
+            Entry::Occupied(_) => Err(Error::AlreadyExists),
         }
     }
-    fn delete(&mut self, id: &String) -> ResultV {
-        self.map.remove(id).map(|_| ()).ok_or(DELETE_ERROR).map_err(Self::report)
+    fn delete(&mut self, id: &str) -> ResultV {
+        self.map.remove(id).map(|_| ()).ok_or(Error::NotFound)
     }
-    fn get(&mut self, id: &String) -> Result<Record> {
+    fn get(&mut self, id: &str) -> Result<Record> {
         match self.map.get(id) {
             Some(v) => Ok(v.clone()),
-            None => Err(NOT_FOUND_ERROR)
+            None => Err(Error::NotFound)
         }
     }
-    fn update(&mut self, r: Record) -> ResultV {
-        let id = r.id.clone();
-        if !self.map.contains_key(&id) {
-            return Err(NOT_FOUND_ERROR);
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        let rec = self.map.get_mut(id).ok_or(Error::NotFound)?;
+        // Exhausted record is never given out, even if it has not been removed yet
+        if rec.max_clicks == 0 {
+            return Err(Error::NotFound);
         }
-        self.map.entry(id).and_modify(|rec| rec.max_clicks = r.max_clicks );
+        rec.max_clicks -= 1;
+        let r = rec.clone();
+        if r.max_clicks == 0 {
+            self.map.remove(id);
+        }
+        Ok(r)
+    }
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        let rec = self.map.get_mut(id).ok_or(Error::NotFound)?;
+        rec.attempts += 1;
+        Ok(rec.attempts)
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        let mut purged = Purged::default();
+        self.map.retain(|_, r| !purged.add(r));
+        Ok(purged)
+    }
+    fn count(&mut self) -> Result<usize> {
+        Ok(self.map.values().filter(|r| !r.expired() && r.max_clicks > 0).count())
+    }
+    fn ping(&mut self) -> ResultV {
         Ok(())
     }
     fn prepare(&mut self) -> ResultV {
@@ -207,118 +386,244 @@ impl DbEngine for MemoryEngine {
 
 }
 impl DbEngine for SqliteEngine {
-    fn new(path: &String) -> Result<Self> {
-        Ok(SqliteEngine {
-            connection: sqlite::Connection::open_with_full_mutex(path.as_str()).map_err(Self::report)?,
-        })
+    fn new(path: &str) -> Result<Self> {
+        let connection = sqlite::Connection::open_with_full_mutex(path).map_err(Self::report)?;
+        Ok(SqliteEngine { connection })
     }
-    fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV {
+    fn insert(&mut self, r: &Record) -> ResultV {
         let mut stmt = self.prepare_statement(INSERT_SQL_QUERY)?;
 
         stmt.bind::<&[(_, Value)]>(&[
-            (":id",         id.as_str().into()),
-            (":data",       msg.get_data().as_str().into()),
-            (":max_clicks", (msg.get_max_clicks() as i64).into()),
-            (":created",    now().into()),
-            (":lifetime",   (msg.get_lifetime() as i64).into()),
+            (":id",         r.id.as_str().into()),
+            (":data",       r.data.as_slice().into()),
+            (":max_clicks", (r.max_clicks as i64).into()),
+            (":created",    r.created.into()),
+            (":lifetime",   (r.lifetime as i64).into()),
+            (":expires_at", r.expires().into()),
+            (":token",      r.token.as_str().into()),
+            (":passphrase", r.passphrase.as_str().into()),
+            (":attempts",   (r.attempts as i64).into()),
+            (":owner",      r.owner.as_str().into()),
+            (":filename",   r.filename.as_str().into()),
+            (":mime",       r.mime.as_str().into()),
+            (":not_before", r.not_before.into()),
         ][..]).map_err(Self::report)?;
 
-        self.check_ok(&mut stmt)
+        match stmt.next() {
+            Ok(_) => Ok(()),
+            Err(e) if e.code == Some(SQLITE_CONSTRAINT) => Err(Error::AlreadyExists),
+            Err(e) => Err(Self::report(e)),
+        }
     }
-    fn delete(&mut self, id: &String) -> ResultV {
+    fn delete(&mut self, id: &str) -> ResultV {
         let mut del_stmt = self.prepare_statement(DELETE_BY_ID_SQL_QUERY)?;
 
         del_stmt.bind::<&[(_, Value)]>(&[
-            (":id", id.as_str().into())
+            (":id", id.into())
         ][..]).map_err(Self::report)?;
 
         self.check_ok(&mut del_stmt)
     }
-    fn get(&mut self, id: &String) -> Result<Record> {
+    fn get(&mut self, id: &str) -> Result<Record> {
         let mut stmt = self.prepare_statement(SELECT_BY_ID_SQL_QUERY)?;
 
         stmt.bind::<&[(_, Value)]>(&[
-            (":id", id.as_str().into())
+            (":id", id.into())
         ][..]).map_err(Self::report)?;
 
-        while let Ok(State::Row) = stmt.next() {
-            let rid = self.read_column::<String>(&stmt, "id")?;
-            let msg: String = self.read_column::<String>(&stmt, "data")?;
-            let max_clicks = self.read_column::<i64>(&stmt, "max_clicks")? as u32;
-            let created = self.read_column::<i64>(&stmt, "created")?;
-            let lifetime = self.read_column::<i64>(&stmt, "lifetime")? as u64;
+        self.read_record(&mut stmt)
+    }
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        let mut stmt = self.prepare_statement(CONSUME_BY_ID_SQL_QUERY)?;
 
-            return Ok(Record{
-                id: rid, data: msg, max_clicks, created, lifetime
-            });
+        stmt.bind::<&[(_, Value)]>(&[
+            (":id", id.into())
+        ][..]).map_err(Self::report)?;
+
+        let r = self.read_record(&mut stmt)?;
+        if r.max_clicks == 0 {
+            let mut del_stmt = self.prepare_statement(DELETE_CONSUMED_BY_ID_SQL_QUERY)?;
+
+            del_stmt.bind::<&[(_, Value)]>(&[
+                (":id", id.into())
+            ][..]).map_err(Self::report)?;
+
+            self.check_ok(&mut del_stmt)?;
         }
-        Err(NOT_FOUND_ERROR)
+        Ok(r)
     }
-    fn update(&mut self, r: Record) -> ResultV {
-        let mut upd_stmt = self.prepare_statement(UPDATE_BY_ID_SQL_QUERY)?;
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        let mut stmt = self.prepare_statement(FAIL_ATTEMPT_BY_ID_SQL_QUERY)?;
 
-        upd_stmt.bind::<&[(_, Value)]>(&[
-            (":max_clicks", (r.max_clicks as i64).into()),
-            (":id",         r.id.as_str().into()),
+        stmt.bind::<&[(_, Value)]>(&[
+            (":id", id.into())
+        ][..]).map_err(Self::report)?;
+
+        Ok(self.read_record(&mut stmt)?.attempts)
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        let mut stmt = self.prepare_statement(PURGE_EXPIRED_SQL_QUERY)?;
+
+        stmt.bind::<&[(_, Value)]>(&[
+            (":now", now().into())
+        ][..]).map_err(Self::report)?;
+
+        self.check_ok(&mut stmt)?;
+        let expired = self.connection.change_count();
+
+        self.connection.execute(PURGE_EXHAUSTED_SQL_QUERY).map_err(Self::report)?;
+        Ok(Purged { expired, exhausted: self.connection.change_count() })
+    }
+    fn count(&mut self) -> Result<usize> {
+        let mut stmt = self.prepare_statement(COUNT_SQL_QUERY)?;
+
+        stmt.bind::<&[(_, Value)]>(&[
+            (":now", now().into())
         ][..]).map_err(Self::report)?;
 
-        self.check_ok(&mut upd_stmt)
+        stmt.next().map_err(Self::report)?;
+        Ok(self.read_column::<i64>(&stmt, "count")? as usize)
+    }
+    fn ping(&mut self) -> ResultV {
+        self.connection.execute(PING_SQL_QUERY).map_err(Self::report)
     }
     fn prepare(&mut self) -> ResultV {
-        self.connection.execute(PREPARE_DB_SQL_QUERY).map_err(Self::report)
+        // Whole migration is one transaction, and the write lock is taken at its start
+        self.connection.set_busy_timeout(MIGRATION_LOCK_TIMEOUT_S as usize * 1000).map_err(Self::report)?;
+        self.connection.execute("BEGIN IMMEDIATE").map_err(Self::report)?;
+        if let Err(e) = self.migrate() {
+            self.connection.execute("ROLLBACK").map_err(Self::report)?;
+            return Err(e);
+        }
+        self.connection.execute("COMMIT").map_err(Self::report)
     }
 }
 impl DbEngine for FileEngine {
-    fn new(path: &String) -> Result<Self> {
-        Ok(FileEngine { dir_path: path.clone() })
+    fn new(path: &str) -> Result<Self> {
+        Ok(FileEngine { dir_path: path.to_string() })
     }
-    fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV {
-        let filepath = self.get_filepath(id);
-        if self.file_exists(&filepath) {
-            // TODO: add logging here
-            return Err(ALREADY_EXISTS_ERROR);
-        }
-
-        serde_json::to_writer(
-            OpenOptions::new().write(true).create(true).open(filepath).map_err(Self::report)?,
-            &Record::new(id, msg)
-        ).map_err(Self::report)
+    fn insert(&mut self, r: &Record) -> ResultV {
+        // File is created only if it does not exist, so existing record is never overwritten
+        let file = match OpenOptions::new().write(true).create_new(true).open(self.get_filepath(&r.id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(Error::AlreadyExists),
+            Err(e) => return Err(Self::report(e)),
+        };
+        serde_json::to_writer(file, r).map_err(Self::report)
     }
-    fn delete(&mut self, id: &String) -> ResultV {
+    fn delete(&mut self, id: &str) -> ResultV {
         let filepath = self.get_filepath(id);
         if !self.file_exists(&filepath) {
-            // TODO: add logging here
-            return Err(DO_NOT_EXISTS_ERROR);
+            return Err(Error::NotFound);
         }
 
         std::fs::remove_file(filepath).map_err(Self::report)
     }
-    fn get(&mut self, id: &String) -> Result<Record> {
+    fn get(&mut self, id: &str) -> Result<Record> {
         let filepath = self.get_filepath(id);
         if !self.file_exists(&filepath) {
-            return Err(NOT_FOUND_ERROR);
+            return Err(Error::NotFound);
         }
 
-        serde_json::from_reader::<_, Record>(
-            OpenOptions::new().read(true).open(filepath).map_err(Self::report)?
-        ).map_err(Self::report)
+        let file = OpenOptions::new().read(true).open(filepath).map_err(Self::report)?;
+        file.lock_shared().map_err(Self::report)?;
+        serde_json::from_reader::<_, Record>(&file).map_err(Self::report)
     }
-    fn update(&mut self, r: Record)-> ResultV {
-        let filepath = self.get_filepath(&r.id);
-        if !self.file_exists(&filepath) {
-            return Err(NOT_FOUND_ERROR);
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        let filepath = self.get_filepath(id);
+        let mut file = match OpenOptions::new().read(true).write(true).open(&filepath) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(Error::NotFound),
+            Err(e) => return Err(Self::report(e)),
+        };
+        // Lock is released when the file is closed
+        file.lock().map_err(Self::report)?;
+
+        let mut record = serde_json::from_reader::<_, Record>(&file).map_err(Self::report)?;
+        // Someone has consumed the last click while we were waiting for the lock
+        if record.max_clicks == 0 {
+            return Err(Error::NotFound);
         }
+        record.max_clicks -= 1;
 
-        let mut record = serde_json::from_reader::<_, Record>(
-            OpenOptions::new().read(true).open(filepath.clone()).map_err(Self::report)?
-        ).map_err(Self::report)?;
+        // Exhausted record is written back before removing, so that anyone
+        // who has already opened the file will not get the data
+        file.seek(SeekFrom::Start(0)).map_err(Self::report)?;
+        file.set_len(0).map_err(Self::report)?;
+        serde_json::to_writer(&file, &record).map_err(Self::report)?;
+        if record.max_clicks == 0 {
+            std::fs::remove_file(filepath).map_err(Self::report)?;
+        }
+        Ok(record)
+    }
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        let filepath = self.get_filepath(id);
+        let mut file = match OpenOptions::new().read(true).write(true).open(&filepath) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(Error::NotFound),
+            Err(e) => return Err(Self::report(e)),
+        };
+        file.lock().map_err(Self::report)?;
 
-        record.max_clicks = r.max_clicks;
+        let mut record = serde_json::from_reader::<_, Record>(&file).map_err(Self::report)?;
+        record.attempts += 1;
 
-        serde_json::to_writer(
-            OpenOptions::new().write(true).open(filepath.clone()).map_err(Self::report)?,
-            &record
-        ).map_err(Self::report)
+        file.seek(SeekFrom::Start(0)).map_err(Self::report)?;
+        file.set_len(0).map_err(Self::report)?;
+        serde_json::to_writer(&file, &record).map_err(Self::report)?;
+        Ok(record.attempts)
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        let mut purged = Purged::default();
+        for entry in std::fs::read_dir(&self.dir_path).map_err(Self::report)? {
+            let path = entry.map_err(Self::report)?.path();
+            let file = match OpenOptions::new().read(true).open(&path) {
+                Ok(f) => f,
+                // Record has been consumed in the meantime
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Self::report(e)),
+            };
+            file.lock().map_err(Self::report)?;
+            let record = match serde_json::from_reader::<_, Record>(&file) {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("[{}] Skip unreadable record file {}: {}", MODULE, path.display(), e);
+                    continue;
+                }
+            };
+            if record.expired() || record.max_clicks == 0 {
+                match std::fs::remove_file(&path) {
+                    Ok(_) => { purged.add(&record); },
+                    Err(e) if e.kind() == ErrorKind::NotFound => {},
+                    Err(e) => return Err(Self::report(e)),
+                }
+            }
+        }
+        Ok(purged)
+    }
+    fn count(&mut self) -> Result<usize> {
+        let mut count = 0;
+        for entry in std::fs::read_dir(&self.dir_path).map_err(Self::report)? {
+            let path = entry.map_err(Self::report)?.path();
+            let file = match OpenOptions::new().read(true).open(&path) {
+                Ok(f) => f,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Self::report(e)),
+            };
+            file.lock_shared().map_err(Self::report)?;
+            if let Ok(record) = serde_json::from_reader::<_, Record>(&file) {
+                if !record.expired() && record.max_clicks > 0 {
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+    fn ping(&mut self) -> ResultV {
+        // Directory must be writable, so try to create and remove a file there
+        let path = self.get_filepath(&format!(".ping-{}", generate_hex_id(8)));
+        OpenOptions::new().write(true).create_new(true).open(&path).map_err(Self::report)?;
+        std::fs::remove_file(&path).map_err(Self::report)
     }
     fn prepare(&mut self) -> ResultV {
         if !self.file_exists(&self.dir_path) {
@@ -328,25 +633,36 @@ impl DbEngine for FileEngine {
     }
 }
 impl DbEngine for MysqlEngine {
-    fn new(path: &String) -> Result<Self> {
-        let pool = mysql::Pool::new(path.as_str()).map_err(Self::report)?;
+    fn new(path: &str) -> Result<Self> {
+        let pool = mysql::Pool::new(path).map_err(Self::report)?;
         Ok(MysqlEngine{
             connection: pool.get_conn().map_err(Self::report)?
         })
     }
-    fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV {
+    fn insert(&mut self, r: &Record) -> ResultV {
         self.connection.exec_drop(
             INSERT_SQL_QUERY,
             params!{
-                "id"=>id,
-                "data"=>msg.get_data(),
-                "max_clicks"=>msg.get_max_clicks(),
-                "created" => now(),
-                "lifetime" => msg.get_lifetime(),
+                "id"=>&r.id,
+                "data"=>&r.data,
+                "max_clicks"=>r.max_clicks,
+                "created" => r.created,
+                "lifetime" => r.lifetime,
+                "expires_at" => r.expires(),
+                "token" => &r.token,
+                "passphrase" => &r.passphrase,
+                "attempts" => r.attempts,
+                "owner" => &r.owner,
+                "filename" => &r.filename,
+                "mime" => &r.mime,
+                "not_before" => r.not_before,
             },
-        ).map_err(Self::report)
+        ).map_err(|e| match e {
+            mysql::Error::MySqlError(ref err) if err.code == MYSQL_DUPLICATE_ENTRY => Error::AlreadyExists,
+            e => Self::report(e),
+        })
     }
-    fn delete(&mut self, id: &String) -> ResultV {
+    fn delete(&mut self, id: &str) -> ResultV {
         self.connection.exec_drop(
             DELETE_BY_ID_SQL_QUERY,
             params!{
@@ -354,37 +670,110 @@ impl DbEngine for MysqlEngine {
             }
         ).map_err(Self::report)
     }
-    fn get(&mut self, id: &String) -> Result<Record> {
-        let result = self.connection.exec_map(
+    fn get(&mut self, id: &str) -> Result<Record> {
+        let result = self.connection.exec(
             SELECT_BY_ID_SQL_QUERY,
             params!{
                 "id" => id,
             },
-            |(id, data, max_clicks, created, lifetime)| Record{
-                id, data, max_clicks, created, lifetime
-            }
         ).map_err(Self::report)?;
-        assert!(result.len() <= 1);
-        match &result[..] {
-            [first] => Ok(first.to_owned()),
-            _ => Err(NOT_FOUND_ERROR)
+        Self::read_record(result)
+    }
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        let mut tx = self.connection.start_transaction(TxOpts::default()).map_err(Self::report)?;
+
+        let result = tx.exec(
+            SELECT_FOR_UPDATE_BY_ID_SQL_QUERY,
+            params!{
+                "id" => id,
+            },
+        ).map_err(Self::report)?;
+        let mut r = Self::read_record(result)?;
+        if r.max_clicks == 0 {
+            return Err(Error::NotFound);
+        }
+
+        r.max_clicks -= 1;
+        if r.max_clicks == 0 {
+            tx.exec_drop(
+                DELETE_BY_ID_SQL_QUERY,
+                params!{
+                    "id" => id,
+                }
+            ).map_err(Self::report)?;
+        } else {
+            tx.exec_drop(
+                UPDATE_BY_ID_SQL_QUERY,
+                params!{
+                    "id" => id,
+                    "max_clicks" => r.max_clicks,
+                },
+            ).map_err(Self::report)?;
         }
+        tx.commit().map_err(Self::report)?;
+        Ok(r)
     }
-    fn update(&mut self, r: Record) -> ResultV {
-        self.connection.exec_drop(
-            UPDATE_BY_ID_SQL_QUERY,
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        let mut tx = self.connection.start_transaction(TxOpts::default()).map_err(Self::report)?;
+
+        tx.exec_drop(
+            FAIL_ATTEMPT_BY_ID_MYSQL_QUERY,
+            params!{
+                "id" => id,
+            }
+        ).map_err(Self::report)?;
+        let result = tx.exec(
+            SELECT_BY_ID_SQL_QUERY,
             params!{
-                "id" => r.id,
-                "max_clicks" => r.max_clicks,
+                "id" => id,
             },
-        ).map_err(Self::report)
+        ).map_err(Self::report)?;
+        let r = Self::read_record(result)?;
+
+        tx.commit().map_err(Self::report)?;
+        Ok(r.attempts)
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        self.connection.exec_drop(
+            PURGE_EXPIRED_SQL_QUERY,
+            params!{
+                "now" => now(),
+            }
+        ).map_err(Self::report)?;
+        let expired = self.connection.affected_rows() as usize;
+
+        self.connection.query_drop(PURGE_EXHAUSTED_SQL_QUERY).map_err(Self::report)?;
+        Ok(Purged { expired, exhausted: self.connection.affected_rows() as usize })
+    }
+    fn count(&mut self) -> Result<usize> {
+        let count: Option<u64> = self.connection.exec_first(
+            COUNT_SQL_QUERY,
+            params!{
+                "now" => now(),
+            }
+        ).map_err(Self::report)?;
+        Ok(count.unwrap_or_default() as usize)
+    }
+    fn ping(&mut self) -> ResultV {
+        self.connection.query_drop(PING_SQL_QUERY).map_err(Self::report)
     }
     fn prepare(&mut self) -> ResultV {
-        self.connection.query_drop(PREPARE_DB_SQL_QUERY).map_err(Self::report)
+        let locked: Option<Option<i64>> = self.connection.exec_first(
+            LOCK_MIGRATIONS_MYSQL_QUERY,
+            params!{
+                "timeout" => MIGRATION_LOCK_TIMEOUT_S,
+            }
+        ).map_err(Self::report)?;
+        if locked.flatten() != Some(1) {
+            return Err(Self::report("timeout while waiting for migration lock"));
+        }
+        let migrated = self.migrate();
+        self.connection.query_drop(UNLOCK_MIGRATIONS_MYSQL_QUERY).map_err(Self::report)?;
+        migrated
     }
 }
 impl DbEngine for PostgresqlEngine {
-    fn new(path: &String) -> Result<Self> {
+    fn new(path: &str) -> Result<Self> {
         let manager = PostgresConnectionManager::new(
             path.parse().map_err(Self::report)?,
             NoTls,
@@ -393,44 +782,210 @@ impl DbEngine for PostgresqlEngine {
             pool: r2d2::Pool::new(manager).map_err(Self::report)?,
         })
     }
-    fn insert(&mut self, id: &String, msg: &ApiAddRequest) -> ResultV {
+    fn insert(&mut self, r: &Record) -> ResultV {
         self.client()?.execute(
             INSERT_PGSQL_QUERY,
-            &[&id, &msg.get_data(), &(msg.get_max_clicks() as i64), &now(), &(msg.get_lifetime() as i64)]
-        ).map(|_| ()).map_err(Self::report)
+            &[&r.id, &r.data, &(r.max_clicks as i64), &r.created, &(r.lifetime as i64), &r.expires(), &r.token, &r.passphrase,
+                &(r.attempts as i64), &r.owner, &r.filename, &r.mime, &r.not_before]
+        ).map(|_| ()).map_err(|e| match e.code() {
+            Some(&SqlState::UNIQUE_VIOLATION) => Error::AlreadyExists,
+            _ => Self::report(e),
+        })
     }
-    fn delete(&mut self, id: &String) -> ResultV {
-        self.client()?.execute(DELETE_BY_ID_PGSQL_QUERY, &[id]).map(|_| ()).map_err(Self::report)
+    fn delete(&mut self, id: &str) -> ResultV {
+        self.client()?.execute(DELETE_BY_ID_PGSQL_QUERY, &[&id]).map(|_| ()).map_err(Self::report)
     }
-    fn get(&mut self, id: &String) -> Result<Record> {
+    fn get(&mut self, id: &str) -> Result<Record> {
         let result = self.client()?.query(
             SELECT_BY_ID_PGSQL_QUERY,
             &[&id]
         ).map_err(Self::report)?;
-        assert!(result.len() <= 1);
-        match &result[..] {
-            [first] => {
-                let lifetime: i64 = first.get("lifetime");
-                let clicks: i64 = first.get("max_clicks");
-                Ok(Record{
-                    id: first.get("id"),
-                    data: first.get("data"),
-                    max_clicks: clicks as u32,
-                    created: first.get("created"),
-                    lifetime: lifetime as u64
-                })
-            },
-            _ => Err(NOT_FOUND_ERROR)
+        Self::read_record(&result)
+    }
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        let mut client = self.client()?;
+        let result = client.query(
+            CONSUME_BY_ID_PGSQL_QUERY,
+            &[&id]
+        ).map_err(Self::report)?;
+        let r = Self::read_record(&result)?;
+        if r.max_clicks == 0 {
+            client.execute(DELETE_CONSUMED_BY_ID_PGSQL_QUERY, &[&id]).map_err(Self::report)?;
         }
+        Ok(r)
     }
-    fn update(&mut self, r: Record) -> ResultV {
-        self.client()?.execute(
-            UPDATE_BY_ID_PGSQL_QUERY,
-            &[&(r.max_clicks as i64), &r.id]
-        ).map(|_| ()).map_err(Self::report)
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        let result = self.client()?.query(
+            FAIL_ATTEMPT_BY_ID_PGSQL_QUERY,
+            &[&id]
+        ).map_err(Self::report)?;
+        Ok(Self::read_record(&result)?.attempts)
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        let mut client = self.client()?;
+        let expired = client.execute(PURGE_EXPIRED_PGSQL_QUERY, &[&now()]).map_err(Self::report)? as usize;
+        let exhausted = client.execute(PURGE_EXHAUSTED_SQL_QUERY, &[]).map_err(Self::report)? as usize;
+        Ok(Purged { expired, exhausted })
+    }
+    fn count(&mut self) -> Result<usize> {
+        let row = self.client()?.query_one(COUNT_PGSQL_QUERY, &[&now()]).map_err(Self::report)?;
+        Ok(row.get::<_, i64>("count") as usize)
+    }
+    fn ping(&mut self) -> ResultV {
+        self.client()?.batch_execute(PING_SQL_QUERY).map_err(Self::report)
+    }
+    fn prepare(&mut self) -> ResultV {
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(Self::report)?;
+        tx.execute(LOCK_MIGRATIONS_PGSQL_QUERY, &[&MIGRATION_LOCK_PGSQL_KEY]).map_err(Self::report)?;
+        tx.batch_execute(CREATE_SCHEMA_VERSION_SQL_QUERY).map_err(Self::report)?;
+        let mut current = tx.query_one(SELECT_SCHEMA_VERSION_SQL_QUERY, &[]).map_err(Self::report)?.get::<_, i64>("version") as u32;
+        if current == 0 {
+            current = migrations::unversioned(Self::has_table(&mut tx)?);
+            if current > 0 {
+                tx.execute(INSERT_SCHEMA_VERSION_PGSQL_QUERY, &[&(current as i64), &now()]).map_err(Self::report)?;
+            }
+        }
+
+        for m in migrations::pending(migrations::PGSQL, current)? {
+            for statement in m.statements {
+                tx.batch_execute(statement).map_err(Self::report)?;
+            }
+            tx.execute(INSERT_SCHEMA_VERSION_PGSQL_QUERY, &[&(m.version as i64), &now()]).map_err(Self::report)?;
+            migrations::log_applied(m);
+        }
+        tx.commit().map_err(Self::report)
+    }
+}
+impl DbEngine for RedbEngine {
+    fn new(path: &str) -> Result<Self> {
+        // Every write transaction is committed with fsync, so records survive crashes
+        Ok(RedbEngine { db: redb::Database::create(path).map_err(Self::report)? })
+    }
+    fn insert(&mut self, r: &Record) -> ResultV {
+        self.write(|records, expiry| {
+            if records.get(r.id.as_str()).map_err(Self::report)?.is_some() {
+                return Err(Error::AlreadyExists);
+            }
+            Self::put_record(records, expiry, r)
+        })
+    }
+    fn delete(&mut self, id: &str) -> ResultV {
+        self.write(|records, expiry| {
+            let r = Self::read_record(records, id)?;
+            Self::remove_record(records, expiry, &r)
+        })
+    }
+    fn get(&mut self, id: &str) -> Result<Record> {
+        let tx = self.db.begin_read().map_err(Self::report)?;
+        let records = tx.open_table(REDB_RECORDS_TABLE).map_err(Self::report)?;
+        Self::read_record(&records, id)
+    }
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        // Write transactions are serialized by redb, so read and decrement are atomic
+        self.write(|records, expiry| {
+            let mut r = Self::read_record(records, id)?;
+            if r.max_clicks == 0 {
+                return Err(Error::NotFound);
+            }
+            r.max_clicks -= 1;
+            if r.max_clicks == 0 {
+                Self::remove_record(records, expiry, &r)?;
+            } else {
+                Self::put_record(records, expiry, &r)?;
+            }
+            Ok(r)
+        })
+    }
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        self.write(|records, expiry| {
+            let mut r = Self::read_record(records, id)?;
+            r.attempts += 1;
+            Self::put_record(records, expiry, &r)?;
+            Ok(r.attempts)
+        })
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        self.write(|records, expiry| {
+            // Exhausted records are deleted by `consume`, so only expired ones are left here
+            let mut expired = Vec::new();
+            for entry in expiry.range(..(now(), "")).map_err(Self::report)? {
+                let (key, _) = entry.map_err(Self::report)?;
+                let (expires, id) = key.value();
+                expired.push((expires, id.to_string()));
+            }
+            for (expires, id) in &expired {
+                records.remove(id.as_str()).map_err(Self::report)?;
+                expiry.remove((*expires, id.as_str())).map_err(Self::report)?;
+            }
+            Ok(Purged { expired: expired.len(), exhausted: 0 })
+        })
+    }
+    fn count(&mut self) -> Result<usize> {
+        let tx = self.db.begin_read().map_err(Self::report)?;
+        let total = tx.open_table(REDB_RECORDS_TABLE).map_err(Self::report)?.len().map_err(Self::report)?;
+        let expired = tx.open_table(REDB_EXPIRY_TABLE).map_err(Self::report)?
+            .range(..(now(), "")).map_err(Self::report)?
+            .count();
+        Ok((total as usize).saturating_sub(expired))
+    }
+    fn ping(&mut self) -> ResultV {
+        self.db.begin_read().map(|_| ()).map_err(Self::report)
+    }
+    fn prepare(&mut self) -> ResultV {
+        self.write(|_, _| Ok(()))
+    }
+}
+impl DbEngine for RedisEngine {
+    fn new(path: &str) -> Result<Self> {
+        let client = redis::Client::open(path).map_err(Self::report)?;
+        Ok(RedisEngine{
+            pool: r2d2::Pool::new(client).map_err(Self::report)?,
+        })
+    }
+    fn insert(&mut self, r: &Record) -> ResultV {
+        // Redis deletes the key itself when the record expires
+        let ttl = (r.expires() - now()).max(1);
+        let value = serde_json::to_string(r).map_err(Self::report)?;
+        let created: Option<String> = redis::cmd("SET").arg(Self::key(&r.id)).arg(value).arg("EX").arg(ttl).arg("NX")
+            .query(&mut *self.connection()?).map_err(Self::report)?;
+        created.map(|_| ()).ok_or(Error::AlreadyExists)
+    }
+    fn delete(&mut self, id: &str) -> ResultV {
+        let deleted: usize = redis::cmd("DEL").arg(Self::key(id)).query(&mut *self.connection()?).map_err(Self::report)?;
+        if deleted == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+    fn get(&mut self, id: &str) -> Result<Record> {
+        let value: Option<String> = redis::cmd("GET").arg(Self::key(id)).query(&mut *self.connection()?).map_err(Self::report)?;
+        Self::read_record(value)
+    }
+    fn consume(&mut self, id: &str) -> Result<Record> {
+        let value: Option<String> = redis::Script::new(REDIS_CONSUME_SCRIPT).key(Self::key(id))
+            .invoke(&mut *self.connection()?).map_err(Self::report)?;
+        Self::read_record(value)
+    }
+    fn fail_attempt(&mut self, id: &str) -> Result<u32> {
+        let attempts: Option<u32> = redis::Script::new(REDIS_FAIL_ATTEMPT_SCRIPT).key(Self::key(id))
+            .invoke(&mut *self.connection()?).map_err(Self::report)?;
+        attempts.ok_or(Error::NotFound)
+    }
+    fn purge(&mut self) -> Result<Purged> {
+        // Expired keys are deleted by redis and exhausted ones by `consume`
+        Ok(Purged::default())
+    }
+    fn count(&mut self) -> Result<usize> {
+        // Redis may be shared with other services, so scanning its whole keyspace on every sweep is too costly,
+        // and a counter kept by scripts would drift as keys expire
+        Err(Error::NotImplemented)
+    }
+    fn ping(&mut self) -> ResultV {
+        redis::cmd("PING").query(&mut *self.connection()?).map_err(Self::report)
     }
     fn prepare(&mut self) -> ResultV {
-        self.client()?.batch_execute(PREPARE_DB_SQL_QUERY).map_err(Self::report)
+        self.ping()
     }
 }
 
@@ -442,45 +997,341 @@ impl SqliteEngine {
     fn read_column<T: ReadableWithIndex>(&self, stmt: &Statement, column: &str) -> Result<T> {
         stmt.read::<T, _>(column).map_err(Self::report)
     }
+    fn read_record(&self, stmt: &mut Statement) -> Result<Record> {
+        if let State::Row = stmt.next().map_err(Self::report)? {
+            let rid = self.read_column::<String>(stmt, "id")?;
+            let data = self.read_column::<Vec<u8>>(stmt, "data")?;
+            let max_clicks = self.read_column::<i64>(stmt, "max_clicks")? as u32;
+            let created = self.read_column::<i64>(stmt, "created")?;
+            let lifetime = self.read_column::<i64>(stmt, "lifetime")? as u64;
+            let token = self.read_column::<String>(stmt, "token")?;
+            let passphrase = self.read_column::<String>(stmt, "passphrase")?;
+            let attempts = self.read_column::<i64>(stmt, "attempts")? as u32;
+            let owner = self.read_column::<String>(stmt, "owner")?;
+            let filename = self.read_column::<String>(stmt, "filename")?;
+            let mime = self.read_column::<String>(stmt, "mime")?;
+            let not_before = self.read_column::<i64>(stmt, "not_before")?;
+
+            return Ok(Record{
+                id: rid, data, max_clicks, created, lifetime, token, passphrase, attempts, owner, filename, mime, not_before
+            });
+        }
+        Err(Error::NotFound)
+    }
     fn check_ok(&self, stmt: &mut Statement) -> ResultV {
         stmt.next().map(|_| ()).map_err(Self::report)
     }
+    fn has_table(&self) -> Result<bool> {
+        let mut stmt = self.prepare_statement(TABLE_EXISTS_SQLITE_QUERY)?;
+        stmt.next().map_err(Self::report)?;
+        Ok(self.read_column::<i64>(&stmt, "count")? > 0)
+    }
+    fn set_version(&self, version: u32) -> ResultV {
+        let mut stmt = self.prepare_statement(INSERT_SCHEMA_VERSION_SQL_QUERY)?;
+
+        stmt.bind::<&[(_, Value)]>(&[
+            (":version", (version as i64).into()),
+            (":applied", now().into()),
+        ][..]).map_err(Self::report)?;
+
+        self.check_ok(&mut stmt)
+    }
+    fn migrate(&mut self) -> ResultV {
+        self.connection.execute(CREATE_SCHEMA_VERSION_SQL_QUERY).map_err(Self::report)?;
+        let mut current = {
+            let mut stmt = self.prepare_statement(SELECT_SCHEMA_VERSION_SQL_QUERY)?;
+            stmt.next().map_err(Self::report)?;
+            self.read_column::<i64>(&stmt, "version")? as u32
+        };
+        if current == 0 {
+            current = migrations::unversioned(self.has_table()?);
+            if current > 0 {
+                self.set_version(current)?;
+            }
+        }
+
+        for m in migrations::pending(migrations::SQLITE, current)? {
+            for statement in m.statements {
+                self.connection.execute(statement).map_err(Self::report)?;
+            }
+            self.set_version(m.version)?;
+            migrations::log_applied(m);
+        }
+        Ok(())
+    }
 }
 impl FileEngine {
-    fn get_filepath(&self, id: &String) -> String {
+    fn get_filepath(&self, id: &str) -> String {
         format!("{}/{}", self.dir_path, id)
     }
-    fn file_exists(&self, filepath: &String) -> bool {
-        std::path::Path::new(filepath.as_str()).exists()
+    fn file_exists(&self, filepath: &str) -> bool {
+        std::path::Path::new(filepath).exists()
+    }
+}
+impl MysqlEngine {
+    fn has_table(&mut self) -> Result<bool> {
+        let count: Option<i64> = self.connection.query_first(TABLE_EXISTS_MYSQL_QUERY).map_err(Self::report)?;
+        Ok(count.unwrap_or(0) > 0)
+    }
+    fn set_version<Q: Queryable>(connection: &mut Q, version: u32) -> ResultV {
+        connection.exec_drop(
+            INSERT_SCHEMA_VERSION_SQL_QUERY,
+            params!{
+                "version" => version,
+                "applied" => now(),
+            },
+        ).map_err(Self::report)
+    }
+    fn migrate(&mut self) -> ResultV {
+        self.connection.query_drop(CREATE_SCHEMA_VERSION_SQL_QUERY).map_err(Self::report)?;
+        let mut current: u32 = self.connection.query_first(SELECT_SCHEMA_VERSION_SQL_QUERY).map_err(Self::report)?.unwrap_or_default();
+        if current == 0 {
+            current = migrations::unversioned(self.has_table()?);
+            if current > 0 {
+                Self::set_version(&mut self.connection, current)?;
+            }
+        }
+
+        for m in migrations::pending(migrations::MYSQL, current)? {
+            // MySQL commits DDL statements implicitly, so a failed migration has to be fixed by hand
+            let mut tx = self.connection.start_transaction(TxOpts::default()).map_err(Self::report)?;
+            for statement in m.statements {
+                tx.query_drop(statement).map_err(Self::report)?;
+            }
+            Self::set_version(&mut tx, m.version)?;
+            tx.commit().map_err(Self::report)?;
+            migrations::log_applied(m);
+        }
+        Ok(())
+    }
+    fn read_column<T: mysql::prelude::FromValue>(row: &mysql::Row, column: &str) -> Result<T> {
+        row.get_opt::<T, _>(column).ok_or_else(|| Self::report(format!("no column `{}`", column)))?.map_err(Self::report)
+    }
+    fn read_record(rows: Vec<mysql::Row>) -> Result<Record> {
+        assert!(rows.len() <= 1);
+        match &rows[..] {
+            [first] => Ok(Record{
+                id: Self::read_column(first, "id")?,
+                data: Self::read_column(first, "data")?,
+                max_clicks: Self::read_column(first, "max_clicks")?,
+                created: Self::read_column(first, "created")?,
+                lifetime: Self::read_column(first, "lifetime")?,
+                token: Self::read_column(first, "token")?,
+                passphrase: Self::read_column(first, "passphrase")?,
+                attempts: Self::read_column(first, "attempts")?,
+                owner: Self::read_column(first, "owner")?,
+                filename: Self::read_column(first, "filename")?,
+                mime: Self::read_column(first, "mime")?,
+                not_before: Self::read_column(first, "not_before")?,
+            }),
+            _ => Err(Error::NotFound)
+        }
+    }
+}
+impl RedbEngine {
+    /// Run `f` in write transaction, which is committed only if `f` succeeds. Tables are created if needed
+    fn write<T>(&self, f: impl FnOnce(&mut RedbRecords, &mut RedbExpiry) -> Result<T>) -> Result<T> {
+        let tx = self.db.begin_write().map_err(Self::report)?;
+        let result = {
+            let mut records = tx.open_table(REDB_RECORDS_TABLE).map_err(Self::report)?;
+            let mut expiry = tx.open_table(REDB_EXPIRY_TABLE).map_err(Self::report)?;
+            f(&mut records, &mut expiry)?
+        };
+        tx.commit().map_err(Self::report)?;
+        Ok(result)
+    }
+    fn read_record(records: &impl ReadableTable<&'static str, &'static [u8]>, id: &str) -> Result<Record> {
+        match records.get(id).map_err(Self::report)? {
+            Some(value) => serde_json::from_slice(value.value()).map_err(Self::report),
+            None => Err(Error::NotFound),
+        }
+    }
+    fn put_record(records: &mut RedbRecords, expiry: &mut RedbExpiry, r: &Record) -> ResultV {
+        let value = serde_json::to_vec(r).map_err(Self::report)?;
+        records.insert(r.id.as_str(), value.as_slice()).map_err(Self::report)?;
+        expiry.insert((r.expires(), r.id.as_str()), ()).map_err(Self::report)?;
+        Ok(())
+    }
+    fn remove_record(records: &mut RedbRecords, expiry: &mut RedbExpiry, r: &Record) -> ResultV {
+        records.remove(r.id.as_str()).map_err(Self::report)?;
+        expiry.remove((r.expires(), r.id.as_str())).map_err(Self::report)?;
+        Ok(())
+    }
+}
+impl RedisEngine {
+    fn connection(&mut self) -> Result<r2d2::PooledConnection<redis::Client>> {
+        self.pool.get().map_err(Self::report)
+    }
+    fn key(id: &str) -> String {
+        format!("{}{}", REDIS_KEY_PREFIX, id)
+    }
+    fn read_record(value: Option<String>) -> Result<Record> {
+        match value {
+            Some(v) => serde_json::from_str(&v).map_err(Self::report),
+            None => Err(Error::NotFound),
+        }
     }
 }
 impl PostgresqlEngine {
     fn client(&mut self) -> Result<r2d2::PooledConnection<PostgresConnectionManager<NoTls>>> {
         self.pool.get().map_err(Self::report)
     }
+    fn has_table(client: &mut impl GenericClient) -> Result<bool> {
+        let row = client.query_one(TABLE_EXISTS_PGSQL_QUERY, &[]).map_err(Self::report)?;
+        Ok(row.get::<_, i64>(0) > 0)
+    }
+    fn read_record(rows: &[postgres::Row]) -> Result<Record> {
+        assert!(rows.len() <= 1);
+        match rows {
+            [first] => {
+                let lifetime: i64 = first.get("lifetime");
+                let clicks: i64 = first.get("max_clicks");
+                let attempts: i64 = first.get("attempts");
+                Ok(Record{
+                    id: first.get("id"),
+                    data: first.get("data"),
+                    max_clicks: clicks as u32,
+                    created: first.get("created"),
+                    lifetime: lifetime as u64,
+                    token: first.get("token"),
+                    passphrase: first.get("passphrase"),
+                    attempts: attempts as u32,
+                    owner: first.get("owner"),
+                    filename: first.get("filename"),
+                    mime: first.get("mime"),
+                    not_before: first.get("not_before"),
+                })
+            },
+            _ => Err(Error::NotFound)
+        }
+    }
+}
+
+/// Numbers of records deleted by `purge`. Exhausted records are normally deleted by `consume`,
+/// so they are counted apart from the ones which expired before they were retrieved
+#[derive(Default)]
+pub struct Purged {
+    pub expired: usize,
+    pub exhausted: usize,
+}
+
+impl Purged {
+    /// Count the record if it has to be purged, return whether it has to
+    fn add(&mut self, r: &Record) -> bool {
+        if r.expired() {
+            self.expired += 1;
+        } else if r.max_clicks == 0 {
+            self.exhausted += 1;
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// Record found by `DB::lookup` with its decrypted data, no click is consumed yet
+pub struct Found {
+    record: Record,
+    data: Vec<u8>,
+}
+
+impl Found {
+    /// Argon2 is slow on purpose, so it is called without database lock held
+    pub fn check_passphrase(&self, passphrase: Option<&String>) -> ResultV {
+        if !self.record.has_passphrase() {
+            return Ok(());
+        }
+        let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+        if !crypto::verify_passphrase(passphrase, &self.record.passphrase)? {
+            return Err(Error::WrongPassphrase);
+        }
+        Ok(())
+    }
+}
+
+/// Decrypted secret data, file secrets also have name and MIME type
+pub struct Secret {
+    pub data: Vec<u8>,
+    pub filename: String,
+    pub mime: String,
 }
 
+impl Secret {
+    pub fn is_file(&self) -> bool { !self.mime.is_empty() }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     id: String,
-    data: String,
+    /// Encrypted data, stored as base64 string by `FileEngine`, `RedbEngine` and `RedisEngine`
+    #[serde(with = "base64_bytes")]
+    data: Vec<u8>,
     max_clicks: u32,
     created: i64,
     lifetime: u64,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    passphrase: String,
+    #[serde(default)]
+    attempts: u32,
+    /// Id of API key which was used to create the record
+    #[serde(default)]
+    owner: String,
+    #[serde(default)]
+    filename: String,
+    #[serde(default)]
+    mime: String,
+    /// Unix time before which the record can not be retrieved
+    #[serde(default)]
+    not_before: i64,
 }
 
 impl Record {
-    pub fn new(id: &String, msg: &ApiAddRequest) -> Self {
+    pub fn new(id: &str, data: Vec<u8>, token: &str, passphrase: String, owner: &str, msg: &ApiAddRequest) -> Self {
+        let created = now();
+        // Lifetime is counted from activation, so the record has to be kept until then as well
+        let delay = (msg.get_not_before() - created).max(0) as u64;
         Record{
-            id: id.clone(),
-            data: msg.get_data().clone(),
+            id: id.to_string(),
+            data,
             max_clicks: msg.get_max_clicks(),
-            created: now(),
-            lifetime: msg.get_lifetime(),
+            created,
+            lifetime: delay + msg.get_lifetime(),
+            token: token.to_string(),
+            passphrase,
+            attempts: 0,
+            owner: owner.to_string(),
+            filename: msg.get_filename().to_string(),
+            mime: msg.get_mime().to_string(),
+            not_before: msg.get_not_before(),
         }
     }
+    pub fn max_clicks(&self) -> u32 { self.max_clicks }
+    pub fn created(&self) -> i64 { self.created }
+    pub fn expires(&self) -> i64 { self.created + (self.lifetime as i64) }
+    pub fn not_before(&self) -> i64 { self.not_before }
+    pub fn has_passphrase(&self) -> bool { !self.passphrase.is_empty() }
+
     fn expired(&self) -> bool {
         now() - self.created > (self.lifetime as i64)
     }
+    /// Record is stored, but its activation time has not come yet
+    fn pending(&self) -> bool {
+        now() < self.not_before
+    }
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
 }
@@ -0,0 +1,79 @@
+use std::fmt;
+
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Record does not exist, is expired or the key does not match
+    NotFound,
+    /// Record with the same id already exists
+    AlreadyExists,
+    /// Record is protected with passphrase, but it was not given
+    PassphraseRequired,
+    /// Given passphrase does not match
+    WrongPassphrase,
+    /// Record can not be retrieved before the given unix time
+    NotYetAvailable(i64),
+    /// API key is missing or unknown
+    Auth,
+    /// Client has sent too many requests
+    RateLimited,
+    /// Route is not supported
+    NotImplemented,
+    /// Request body or secret data exceeds configured limit
+    PayloadTooLarge,
+    /// Requested lifetime or clicks are out of server policy
+    Policy(&'static str),
+    /// Input request is malformed
+    Parse(String),
+    /// Database backend failed
+    Backend { kind: &'static str, source: BoxError },
+    /// Encryption or hashing failed
+    Crypto(&'static str),
+    /// Service is misconfigured
+    Config(String),
+    /// Any other server side failure
+    Internal(&'static str),
+}
+
+impl Error {
+    pub fn backend(kind: &'static str, source: impl Into<BoxError>) -> Self {
+        Error::Backend { kind, source: source.into() }
+    }
+
+    pub fn parse(e: impl fmt::Display) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound             => write!(f, "not found"),
+            Error::AlreadyExists        => write!(f, "already exists"),
+            Error::PassphraseRequired   => write!(f, "passphrase required"),
+            Error::WrongPassphrase      => write!(f, "wrong passphrase"),
+            Error::NotYetAvailable(t)   => write!(f, "not available until {}", t),
+            Error::Auth                 => write!(f, "unauthorized"),
+            Error::RateLimited          => write!(f, "too many requests"),
+            Error::NotImplemented       => write!(f, "not implemented"),
+            Error::PayloadTooLarge      => write!(f, "payload too large"),
+            Error::Policy(e)            => write!(f, "policy violation: {}", e),
+            Error::Parse(e)             => write!(f, "parse error: {}", e),
+            Error::Backend{kind, source}=> write!(f, "{} error: {}", kind, source),
+            Error::Crypto(e)            => write!(f, "{}", e),
+            Error::Config(e)            => write!(f, "config error: {}", e),
+            Error::Internal(e)          => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Backend{source, ..} => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
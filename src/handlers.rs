@@ -1,97 +1,489 @@
-use tiny_http::{Request, Response, StatusCode};
+use std::net::IpAddr;
 
-use crate::api::ApiAddRequest;
+use tiny_http::{Header, Method, Request, Response, StatusCode};
+
+use crate::api::{ApiAddRequest, ApiGetRequest};
+use crate::auth;
 use crate::context::Context;
-use crate::db::NOT_FOUND_ERROR;
+use crate::crypto;
+use crate::db::Secret;
+use crate::error::Error;
 use crate::logger::get_reporter;
-use crate::utils::{generate_hex_id, Result, ResultV};
+use crate::metrics::{Event, METRICS};
+use crate::pages;
+use crate::server;
+use crate::utils::{generate_hex_id, generate_id, Result, ResultV};
 
 
 const MODULE: &str = "HANDLERS";
 
-const URL_ID_LENGTH: u32 = 64;
+/// Number of ids tried before giving up, collision of random ids is expected to be extremely rare
+const MAX_INSERT_ATTEMPTS: u32 = 5;
+const URL_KEY_LENGTH: u32 = 32;
+const MANAGEMENT_TOKEN_LENGTH: u32 = 32;
+
+pub const MANAGEMENT_TOKEN_HEADER: &str = "X-Management-Token";
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const WWW_AUTHENTICATE_HEADER: &str = "WWW-Authenticate";
+const SENSITIVE_HEADERS: [&str; 2] = [MANAGEMENT_TOKEN_HEADER, auth::AUTHORIZATION_HEADER];
+
+const MIME_JSON: &str = "application/json";
+const MIME_HTML: &str = "text/html";
+const MIME_TEXT: &str = "text/plain";
+const MIME_PROMETHEUS: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const BACKEND_UNAVAILABLE_CODE: &str = "backend_unavailable";
 
 pub const HTTP_200: u16 = 200;
 pub const HTTP_400: u16 = 400;
+pub const HTTP_401: u16 = 401;
+pub const HTTP_403: u16 = 403;
 pub const HTTP_404: u16 = 404;
+pub const HTTP_409: u16 = 409;
+pub const HTTP_413: u16 = 413;
+pub const HTTP_429: u16 = 429;
 pub const HTTP_500: u16 = 500;
 pub const HTTP_501: u16 = 501;
+pub const HTTP_503: u16 = 503;
 
 
 pub fn respond(r: Request, ctx: &mut Context, code: u16) -> ResultV {
     if code != HTTP_200 {
         ctx.resp.hide_sensitive();
+        // Lets client refer to the request in logs when reporting a problem
+        ctx.resp.set_request_id(ctx.qid.clone());
+    }
+    let data = serde_json::to_string(&ctx.resp).map_err(get_reporter(MODULE, "Respond", |_| Error::Internal("serde error")))?;
+    respond_text(r, ctx, code, MIME_JSON, data)
+}
+
+pub fn respond_html(r: Request, ctx: &mut Context, code: u16, html: String) -> ResultV {
+    respond_text(r, ctx, code, "text/html; charset=UTF-8", html)
+}
+
+pub fn respond_plain(r: Request, ctx: &mut Context, code: u16, text: String) -> ResultV {
+    respond_text(r, ctx, code, "text/plain; charset=UTF-8", text)
+}
+
+fn respond_text(r: Request, ctx: &mut Context, code: u16, content_type: &str, text: String) -> ResultV {
+    let header = Header::from_bytes("Content-Type", content_type).map_err(|_| Error::Internal("header error"))?;
+    let size = text.len();
+    send(r, ctx, code, Response::from_string(text).with_header(header), size)
+}
+
+/// `Content-Disposition` header value with ASCII file name for old clients and UTF-8 one (RFC 5987) for the rest
+fn content_disposition(filename: &str) -> String {
+    if filename.is_empty() {
+        return "attachment".to_string();
     }
-    let data = serde_json::to_string(&ctx.resp).map_err(get_reporter(MODULE, "Respond", "serde error"))?;
-    let response = Response::from_string(&data).with_status_code(StatusCode(code));
-    let result = r.respond(response).map_err(get_reporter(MODULE, "Respond", "respond error"));
+    let ascii: String = filename.chars()
+        .filter(|c| !matches!(c, '"' | '\\') && !c.is_control())
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let encoded: String = filename.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", ascii, encoded)
+}
+
+/// Send file secret as attachment, so browser saves it instead of showing. MIME type is given by uploader,
+/// so browser is not allowed to guess another one either
+fn respond_file(r: Request, ctx: &mut Context, secret: Secret) -> ResultV {
+    let disposition = content_disposition(&secret.filename);
+    let content_type = Header::from_bytes("Content-Type", secret.mime).map_err(|_| Error::Internal("header error"))?;
+    let disposition = Header::from_bytes("Content-Disposition", disposition).map_err(|_| Error::Internal("header error"))?;
+    let nosniff = Header::from_bytes("X-Content-Type-Options", "nosniff").map_err(|_| Error::Internal("header error"))?;
+
+    let size = secret.data.len();
+    let response = Response::from_data(secret.data).with_header(content_type).with_header(disposition).with_header(nosniff);
+    send(r, ctx, HTTP_200, response, size)
+}
+
+fn send(r: Request, ctx: &mut Context, code: u16, response: Response<std::io::Cursor<Vec<u8>>>, size: usize) -> ResultV {
+    let url = r.url().to_string();
+    // Every response refers to its request in logs, whatever format the body is in
+    let request_id = Header::from_bytes(REQUEST_ID_HEADER, ctx.qid.as_str()).map_err(|_| Error::Internal("header error"))?;
+    let mut response = response.with_status_code(StatusCode(code)).with_header(request_id);
+    // 401 has to name the authentication scheme client is expected to use (RFC 9110)
+    if code == HTTP_401 {
+        let challenge = Header::from_bytes(WWW_AUTHENTICATE_HEADER, "Bearer").map_err(|_| Error::Internal("header error"))?;
+        response.add_header(challenge);
+    }
+    let result = r.respond(response).map_err(get_reporter(MODULE, "Respond", |_| Error::Internal("respond error")));
 
     ctx.fix();
-    info!("Respond to [qid={}]: time: {}ms; status: {}; sent: {} bytes", ctx.qid, ctx.time_ms(), code, data.as_bytes().len());
+    METRICS.observe(&url, ctx.time_ms());
+    if code == HTTP_500 {
+        METRICS.count(Event::Error);
+    }
+    info!("Respond to [qid={}]: time: {}ms; status: {}; sent: {} bytes", ctx.qid, ctx.time_ms(), code, size);
 
     result
 }
 
+fn client_ip(r: &Request) -> Option<IpAddr> {
+    server::client_addr(r).map(|a| a.ip())
+}
+
+/// Take a token from client bucket, false means that request has to be rejected with 429
+fn allowed(r: &Request, ctx: &Context) -> bool {
+    client_ip(r).is_none_or(|ip| ctx.limiter.allow(ip))
+}
+
+/// Let the limiter know if the client has found the link, so link guessing can be banned
+fn track_lookup(r: &Request, ctx: &Context, code: u16) {
+    match (client_ip(r), code) {
+        (Some(ip), HTTP_404) => ctx.limiter.not_found(ip),
+        (Some(ip), HTTP_200) => ctx.limiter.found(ip),
+        _ => {},
+    }
+}
+
+/// HTTP status code, machine readable code and human readable description of the error
+fn describe_error(e: &Error) -> (u16, &'static str, &'static str) {
+    match e {
+        Error::NotFound             => (HTTP_404, "not_found", "Link was not found or has been deleted"),
+        Error::AlreadyExists        => (HTTP_409, "already_exists", "Link already exists"),
+        Error::PassphraseRequired   => (HTTP_403, "passphrase_required", "Passphrase is required"),
+        Error::WrongPassphrase      => (HTTP_403, "wrong_passphrase", "Wrong passphrase"),
+        Error::NotYetAvailable(_)   => (HTTP_403, "not_yet_available", "Secret is not available yet"),
+        Error::Auth                 => (HTTP_401, "unauthorized", "Unauthorized"),
+        Error::RateLimited          => (HTTP_429, "too_many_requests", "Too many requests"),
+        Error::NotImplemented       => (HTTP_501, "not_implemented", "Method is not implemented"),
+        Error::PayloadTooLarge      => (HTTP_413, "payload_too_large", "Payload is too large"),
+        Error::Policy(reason)       => (HTTP_400, "policy_violation", reason),
+        Error::Parse(_)             => (HTTP_400, "invalid_request", "Failed to parse input request"),
+        Error::Backend{..}          => (HTTP_500, "backend_error", "server error"),
+        Error::Crypto(_) | Error::Config(_) | Error::Internal(_) => (HTTP_500, "internal_error", "server error"),
+    }
+}
+
+/// Describe the error in response and return HTTP status code for it
+fn set_error(ctx: &mut Context, e: &Error) -> u16 {
+    let (code, error_code, status) = describe_error(e);
+    if code == HTTP_500 {
+        error!("[{}] Request [qid={}] failed: {}", MODULE, ctx.qid, e);
+    }
+    ctx.resp.set_status(status.to_string());
+    ctx.resp.set_error_code(error_code);
+    // Tell when to come back, the link is known to be valid at this point
+    if let Error::NotYetAvailable(not_before) = e {
+        ctx.resp.set_not_before(*not_before);
+    }
+    code
+}
+
+pub fn respond_error(r: Request, ctx: &mut Context, e: Error) -> ResultV {
+    let code = set_error(ctx, &e);
+    respond(r, ctx, code)
+}
+
 pub fn handle_method_add(mut r: Request, ctx: &mut Context) -> ResultV {
-    let parsed = ApiAddRequest::parse_from(&mut r);
-    let mut code = HTTP_400;
-
-    match parsed {
-        Ok(json) => {
-            ctx.resp.set_expired(ctx.resp.created() + (json.get_lifetime() as i64));
-
-            code = match create_url_for_msg(&json, ctx) {
-                Ok(url) => {
-                    ctx.resp.set_message(url);
-                    HTTP_200
-                },
-                Err(e) => {
-                    error!("[{}] Failed to create url for user request: {}", MODULE, e);
-                    ctx.resp.set_status(e.to_string());
-                    HTTP_500
-                }
-            };
-        }
-        Err(_) => ctx.resp.set_status("Failed to parse input request".to_string()),
+    if !allowed(&r, ctx) {
+        return respond_error(r, ctx, Error::RateLimited);
     }
+    let owner = match auth::authenticate(&ctx.cfg.auth, &r) {
+        Ok(owner) => owner,
+        Err(e) => return respond_error(r, ctx, e),
+    };
+    let mut json = match ApiAddRequest::parse_from(&mut r, &ctx.cfg.server) {
+        Ok(json) => json,
+        Err(e) => return respond_error(r, ctx, e),
+    };
+    if let Err(e) = json.apply_policy(&ctx.cfg.policy) {
+        return respond_error(r, ctx, e);
+    }
+
+    // Echo effective values, they may differ from requested ones
+    ctx.resp.set_lifetime(json.get_lifetime());
+    ctx.resp.set_clicks(json.get_max_clicks());
+    let start = ctx.resp.created().max(json.get_not_before());
+    ctx.resp.set_expired(start + (json.get_lifetime() as i64));
+    let code = match create_url_for_msg(&json, &owner, ctx) {
+        Ok(url) => {
+            ctx.resp.set_message(url);
+            ctx.resp.set_not_before(json.get_not_before());
+            METRICS.count(Event::Created);
+            HTTP_200
+        },
+        Err(e) => set_error(ctx, &e),
+    };
     respond(r, ctx, code)
 }
 
-fn create_url_for_msg(msg: &ApiAddRequest, ctx: &mut Context) -> Result<String> {
-    let id = generate_hex_id(URL_ID_LENGTH);
+fn create_url_for_msg(msg: &ApiAddRequest, owner: &str, ctx: &mut Context) -> Result<String> {
+    // Key is a part of the link only, it is never stored on the server side
+    let key = generate_hex_id(URL_KEY_LENGTH);
 
-    ctx.db()?.insert(&id, msg).map_err(
-        get_reporter(MODULE, "Server", "server error")
-    )?;
+    // Token lets the owner delete the secret before it is used
+    let token = generate_hex_id(MANAGEMENT_TOKEN_LENGTH);
 
-    let url = format!("{}/get/{}", ctx.cfg.server.address, id);
+    // Argon2 is slow on purpose, so passphrase is hashed before the database is locked
+    let passphrase = match msg.get_passphrase() {
+        Some(p) => crypto::hash_passphrase(p)?,
+        None => String::new(),
+    };
+
+    // Engines never overwrite existing records, so on collision the id is generated again.
+    // Id is bound to the ciphertext, so the data is encrypted again as well
+    let cfg = ctx.cfg.clone();
+    let mut attempt = 1;
+    let id = loop {
+        let id = generate_id(cfg.ids.length, cfg.ids.alphabet.as_bytes());
+        let data = crypto::encrypt(&key, &id, msg.get_content())?;
+        let inserted = ctx.db()?.insert(&id, data, &token, &passphrase, owner, msg);
+        match inserted {
+            Ok(()) => break id,
+            Err(Error::AlreadyExists) if attempt < MAX_INSERT_ATTEMPTS => {
+                warn!("[{}] Link id collision [qid={}], attempt {} of {}", MODULE, ctx.qid, attempt, MAX_INSERT_ATTEMPTS);
+                attempt += 1;
+            },
+            Err(Error::AlreadyExists) => {
+                // Nothing in the request conflicts with stored data, so it is a server error
+                error!("[{}] Link id collision [qid={}], giving up after {} attempts", MODULE, ctx.qid, attempt);
+                return Err(Error::Internal("link id collision"));
+            },
+            Err(e) => return Err(e),
+        }
+    };
+    if !owner.is_empty() {
+        info!("[{}] Secret [qid={}] created with API key `{}`", MODULE, ctx.qid, owner);
+    }
+    ctx.resp.set_token(token);
+
+    let url = format!("{}/get/{}/{}", ctx.cfg.server.address, id, key);
     Ok(url)
 }
 
-pub fn handle_method_get(r: Request, ctx: &mut Context) -> ResultV  {
-    let parts: Vec<&str> = r.url().split("/").collect();
+/// Header without secret value, so it can be written to logs
+pub fn loggable_header(h: &Header) -> String {
+    if SENSITIVE_HEADERS.iter().any(|name| h.field.equiv(name)) {
+        format!("{}: ***", h.field)
+    } else {
+        h.to_string()
+    }
+}
+
+/// Url without the key of one-time link and without query of /add request, so it can be written to logs
+pub fn loggable_url(url: &str) -> String {
+    if let Some((path @ "/add", _)) = url.split_once('?') {
+        return format!("{}?***", path);
+    }
+    let parts: Vec<&str> = url.split('/').collect();
+    match parts[..] {
+        ["", route @ ("get" | "info"), id, _, ..] => format!("/{}/{}/***", route, id),
+        _ => url.to_string(),
+    }
+}
+
+fn accept_header(r: &Request) -> &str {
+    r.headers().iter().find(|h| h.field.equiv("Accept")).map(|h| h.value.as_str()).unwrap_or_default()
+}
+
+/// Quality of the content type in `Accept` header and specificity of the media range which matched it.
+/// The most specific matching media range decides the quality
+fn quality(accept: &str, mime: &str) -> (f32, u8) {
+    let kind = mime.split('/').next().unwrap_or_default();
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media = params.next().unwrap_or_default().trim();
+        let specificity = if media == mime {
+            3
+        } else if media.strip_suffix("/*") == Some(kind) {
+            2
+        } else if media == "*/*" {
+            1
+        } else {
+            continue;
+        };
+        let q = params.find_map(|p| p.trim().strip_prefix("q=")?.parse::<f32>().ok()).unwrap_or(1.0);
+        if best.is_none_or(|(s, _)| specificity > s) {
+            best = Some((specificity, q));
+        }
+    }
+    best.map_or((0.0, 0), |(s, q)| (q, s))
+}
+
+/// Pick the offered content type client prefers. Of equally preferred types the one named explicitly wins over
+/// the ones matched by wildcard, and the first one wins if client does not care
+fn negotiate<'a>(r: &Request, offered: &[&'a str]) -> &'a str {
+    let accept = accept_header(r);
+    if accept.trim().is_empty() {
+        return offered[0];
+    }
+
+    let mut chosen = (offered[0], (0.0, 0));
+    for mime in offered {
+        let preference = quality(accept, mime);
+        if preference.0 > 0.0 && preference > chosen.1 {
+            chosen = (mime, preference);
+        }
+    }
+    chosen.0
+}
+
+/// Database is locked only for lookup and for consuming, passphrase is verified in between
+fn select_secret(ctx: &mut Context, id: &str, key: &str, passphrase: Option<&String>) -> Result<Secret> {
+    let found = ctx.db()?.lookup(id, key)?;
+    match found.check_passphrase(passphrase) {
+        Ok(()) => ctx.db()?.consume(found),
+        Err(Error::WrongPassphrase) => Err(ctx.db()?.fail_passphrase(&found)),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn handle_method_get(mut r: Request, ctx: &mut Context) -> ResultV  {
+    if !allowed(&r, ctx) {
+        return respond_error(r, ctx, Error::RateLimited);
+    }
+    let url = r.url().to_string();
+    let parts: Vec<&str> = url.split('/').collect();
     let id = parts[2];
+    let key = parts.get(3).copied().unwrap_or_default();
+
+    // Chats and mail scanners follow links automatically, so plain GET only shows the landing page.
+    // Secret is revealed by POST from this page or by API call, which prefers JSON or raw text to HTML.
+    // HTML is offered first, so clients which accept anything get the page
+    if *r.method() == Method::Get && negotiate(&r, &[MIME_HTML, MIME_JSON, MIME_TEXT]) == MIME_HTML {
+        let with_passphrase = ctx.db()?.info(id).map(|rec| rec.has_passphrase()).unwrap_or(false);
+        return respond_html(r, ctx, HTTP_200, pages::reveal(with_passphrase, None));
+    }
 
-    let res = ctx.db()?.select(&id.to_string());
+    let req = match ApiGetRequest::parse_from(&mut r, &ctx.cfg.server) {
+        Ok(req) => req,
+        Err(e) => return respond_error(r, ctx, e),
+    };
+
+    let res = select_secret(ctx, id, key, req.get_passphrase());
+    let mut file = None;
+    let mut failure = None;
     let code =  match res {
-        Ok(message) => {
-            ctx.resp.set_message(message);
+        Ok(secret) => {
+            if secret.is_file() {
+                file = Some(secret);
+            } else {
+                ctx.resp.set_message(String::from_utf8_lossy(&secret.data).into_owned());
+            }
+            METRICS.count(Event::Retrieved);
             HTTP_200
         },
         Err(e) => {
-            if e != NOT_FOUND_ERROR {
-                error!("[{}] Error while doing select: {}", MODULE, e);
-                ctx.resp.set_status("server error".to_string());
-                HTTP_500
-            } else {
-                ctx.resp.set_status("Link was not found or has been deleted".to_string());
-                HTTP_404
+            if let Error::NotFound = e {
+                METRICS.count(Event::NotFound);
             }
-        }
+            let code = set_error(ctx, &e);
+            failure = Some(e);
+            code
+        },
     };
 
+    track_lookup(&r, ctx, code);
+
+    // Files are always sent as is, whatever format client asks for
+    if let Some(secret) = file {
+        return respond_file(r, ctx, secret);
+    }
+
     // Do not want to show sensitive fields in response
     ctx.resp.hide_sensitive();
 
+    match negotiate(&r, &[MIME_JSON, MIME_TEXT, MIME_HTML]) {
+        MIME_HTML => {
+            let html = match failure {
+                None => pages::secret(ctx.resp.message()),
+                // Let user try another passphrase
+                Some(Error::PassphraseRequired | Error::WrongPassphrase) => pages::reveal(true, Some(ctx.resp.status())),
+                Some(Error::NotYetAvailable(not_before)) => pages::not_yet_available(ctx.resp.status(), not_before),
+                Some(_) => pages::error(ctx.resp.status()),
+            };
+            respond_html(r, ctx, code, html)
+        },
+        // Raw secret, so it can be saved to a file as is
+        MIME_TEXT if code == HTTP_200 => {
+            let text = ctx.resp.message().clone();
+            respond_plain(r, ctx, code, text)
+        },
+        MIME_TEXT => {
+            let text = format!("{}\n", ctx.resp.status());
+            respond_plain(r, ctx, code, text)
+        },
+        _ => respond(r, ctx, code),
+    }
+}
+
+pub fn handle_method_info(r: Request, ctx: &mut Context) -> ResultV  {
+    if !allowed(&r, ctx) {
+        return respond_error(r, ctx, Error::RateLimited);
+    }
+    let parts: Vec<&str> = r.url().split('/').collect();
+    let id = parts[2];
+
+    let res = ctx.db()?.info(id);
+    let code =  match res {
+        Ok(record) => {
+            ctx.resp.set_created(record.created());
+            ctx.resp.set_expired(record.expires());
+            ctx.resp.set_clicks(record.max_clicks());
+            ctx.resp.set_not_before(record.not_before());
+            HTTP_200
+        },
+        Err(e) => set_error(ctx, &e),
+    };
+    track_lookup(&r, ctx, code);
+
+    respond(r, ctx, code)
+}
+
+pub fn handle_method_delete(r: Request, ctx: &mut Context) -> ResultV  {
+    if !allowed(&r, ctx) {
+        return respond_error(r, ctx, Error::RateLimited);
+    }
+    let parts: Vec<&str> = r.url().split('/').collect();
+    let id = parts[2];
+    let token = r.headers().iter()
+        .find(|h| h.field.equiv(MANAGEMENT_TOKEN_HEADER))
+        .map(|h| h.value.to_string())
+        .unwrap_or_default();
+
+    let res = ctx.db()?.revoke(id, &token);
+    let code =  match res {
+        Ok(_) => {
+            ctx.resp.set_message("Link has been deleted".to_string());
+            HTTP_200
+        },
+        Err(e) => set_error(ctx, &e),
+    };
+    track_lookup(&r, ctx, code);
+
+    respond(r, ctx, code)
+}
+
+pub fn handle_method_metrics(r: Request, ctx: &mut Context) -> ResultV {
+    let text = METRICS.render(&ctx.cfg.database.kind);
+    respond_text(r, ctx, HTTP_200, MIME_PROMETHEUS, text)
+}
+
+/// Liveness probe: the process is able to handle requests
+pub fn handle_method_healthz(r: Request, ctx: &mut Context) -> ResultV {
+    respond(r, ctx, HTTP_200)
+}
+
+/// Readiness probe: database backend is reachable
+pub fn handle_method_readyz(r: Request, ctx: &mut Context) -> ResultV {
+    // Poisoned database lock makes the service unable to serve requests as well, so it is reported the same way
+    let res = ctx.db().and_then(|mut db| db.ping());
+    let code = match res {
+        Ok(_) => HTTP_200,
+        Err(e) => {
+            error!("[{}] Database is not available: {}", MODULE, e);
+            ctx.resp.set_status("Database is not available".to_string());
+            ctx.resp.set_error_code(BACKEND_UNAVAILABLE_CODE);
+            HTTP_503
+        }
+    };
     respond(r, ctx, code)
 }
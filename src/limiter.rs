@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config;
+
+
+const MODULE: &str = "LIMITER";
+
+// Forget idle clients once there are too many of them. If none of them is idle, the one seen longest ago
+// is forgotten (banned clients go last), so the table never holds more clients than this
+const MAX_CLIENTS: usize = 10000;
+
+
+struct Client {
+    tokens: f64,
+    updated: Instant,
+    not_found: u32,
+    banned_until: Option<Instant>,
+}
+
+impl Client {
+    fn new(burst: f64) -> Self {
+        Client { tokens: burst, updated: Instant::now(), not_found: 0, banned_until: None }
+    }
+
+    fn banned(&self, now: Instant) -> bool {
+        self.banned_until.is_some_and(|t| now < t)
+    }
+
+    fn refill(&mut self, now: Instant, cfg: &config::RateLimit) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * cfg.rate).min(cfg.burst as f64);
+        self.updated = now;
+    }
+
+    fn idle(&self, now: Instant, cfg: &config::RateLimit) -> bool {
+        let full_after = (cfg.burst as f64 - self.tokens) / cfg.rate;
+        !self.banned(now) && self.not_found == 0 && now.duration_since(self.updated).as_secs_f64() >= full_after
+    }
+}
+
+/// Token-bucket rate limiter keyed by client IP. Also bans clients which get too many "not found" responses
+/// in a row, as they are most likely guessing links
+pub struct RateLimiter {
+    cfg: config::RateLimit,
+    clients: Mutex<HashMap<IpAddr, Client>>,
+}
+
+impl RateLimiter {
+    pub fn new(cfg: &config::RateLimit) -> Self {
+        RateLimiter { cfg: cfg.clone(), clients: Mutex::new(HashMap::new()) }
+    }
+
+    /// Take a token from client bucket, return false if request has to be rejected
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        if !self.cfg.enabled {
+            return true;
+        }
+        let mut clients = match self.clients.lock() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("[{}] Clients lock error: {}", MODULE, e);
+                return true;
+            }
+        };
+
+        let now = Instant::now();
+        if clients.len() >= MAX_CLIENTS {
+            clients.retain(|_, c| !c.idle(now, &self.cfg));
+        }
+        if clients.len() >= MAX_CLIENTS && !clients.contains_key(&ip) {
+            if let Some(oldest) = clients.iter().min_by_key(|(_, c)| (c.banned(now), c.updated)).map(|(ip, _)| *ip) {
+                clients.remove(&oldest);
+            }
+        }
+
+        let client = clients.entry(ip).or_insert_with(|| Client::new(self.cfg.burst as f64));
+        if client.banned(now) {
+            return false;
+        }
+        client.refill(now, &self.cfg);
+        if client.tokens < 1.0 {
+            return false;
+        }
+        client.tokens -= 1.0;
+        true
+    }
+
+    /// Count consecutive "not found" responses of the client and ban it when there are too many
+    pub fn not_found(&self, ip: IpAddr) {
+        if !self.cfg.enabled || self.cfg.max_not_found == 0 {
+            return;
+        }
+        if let Ok(mut clients) = self.clients.lock() {
+            let client = clients.entry(ip).or_insert_with(|| Client::new(self.cfg.burst as f64));
+            client.not_found += 1;
+            if client.not_found >= self.cfg.max_not_found {
+                warn!("[{}] Ban {} for {}s after {} links not found", MODULE, ip, self.cfg.ban_time, client.not_found);
+                client.not_found = 0;
+                client.banned_until = Some(Instant::now() + Duration::from_secs(self.cfg.ban_time));
+            }
+        }
+    }
+
+    /// Reset "not found" counter of the client
+    pub fn found(&self, ip: IpAddr) {
+        if let Ok(mut clients) = self.clients.lock() {
+            if let Some(client) = clients.get_mut(&ip) {
+                client.not_found = 0;
+            }
+        }
+    }
+}
@@ -3,7 +3,8 @@ use std::fs::OpenOptions;
 use simplelog::{ColorChoice, LevelFilter, TerminalMode, TermLogger, WriteLogger};
 
 use crate::config::Config;
-use crate::utils::{ErrorStr, ResultV};
+use crate::error::Error;
+use crate::utils::ResultV;
 
 
 pub fn init_logger(cfg: &Config) -> ResultV {
@@ -20,10 +21,13 @@ pub fn init_logger(cfg: &Config) -> ResultV {
     }
 }
 
-pub fn get_reporter<E: std::fmt::Display>(module: &'static str, item: &'static str, msg: ErrorStr) -> impl Fn(E) -> ErrorStr {
+/// Log the error and convert it to `Error` with the given function
+pub fn get_reporter<E: std::fmt::Display>(
+    module: &'static str, item: &'static str, convert: impl FnOnce(E) -> Error
+) -> impl FnOnce(E) -> Error {
     move |e: E| {
         error!("[{}] {} error: {}", module, item, e);
-        msg
+        convert(e)
     }
 }
 
@@ -41,13 +45,13 @@ fn init_term_logger(level: LevelFilter) -> ResultV {
         level,
         prepare_logger_config(),
         TerminalMode::Stderr, ColorChoice::Auto
-    ).map_err(|_| "init logger error")
+    ).map_err(|_| Error::Internal("init logger error"))
 }
 
-fn init_file_logger(level: LevelFilter, filename: &String) -> ResultV {
+fn init_file_logger(level: LevelFilter, filename: &str) -> ResultV {
     WriteLogger::init(
         level,
         prepare_logger_config(),
-        OpenOptions::new().write(true).create(true).append(true).open(filename).map_err(|_|"io error")?
-    ).map_err(|_| "init logger error")
+        OpenOptions::new().create(true).append(true).open(filename).map_err(|_| Error::Internal("io error"))?
+    ).map_err(|_| Error::Internal("init logger error"))
 }
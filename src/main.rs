@@ -1,51 +1,85 @@
 #[macro_use] extern crate log;
 
 pub mod api;
+pub mod auth;
 pub mod config;
 pub mod context;
+pub mod crypto;
 pub mod db;
+pub mod error;
 pub mod handlers;
+pub mod limiter;
 pub mod logger;
+pub mod metrics;
+pub mod migrations;
+pub mod multipart;
+pub mod pages;
+pub mod server;
+pub mod sweeper;
 pub mod utils;
 
 use std::sync::{Arc, Mutex};
 
 use clap::Parser;
-use tiny_http::{Method, Request, Server};
+use tiny_http::{Method, Request};
 
 use crate::db::DB;
+use crate::limiter::RateLimiter;
 use crate::context::Context;
 use crate::config::Config;
-use crate::handlers::{handle_method_add, handle_method_get, respond, HTTP_501};
+use crate::error::Error;
+use crate::handlers::{
+    handle_method_add, handle_method_delete, handle_method_get, handle_method_healthz, handle_method_info,
+    handle_method_metrics, handle_method_readyz, loggable_header, loggable_url, respond_error,
+};
 use crate::utils::ResultV;
 
 
 const MODULE: &str = "MAIN";
 
+/// Links with less random ids are easier to guess and collide more often
+const MIN_ID_ENTROPY_BITS: f64 = 128.0;
+
 /// Simple service for generating one-time access link to your secret data
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about)]
 struct Args {
     /// Path to the configurational file
     config_fn: String,
+
+    /// Bring database schema up to date and exit
+    #[arg(long)]
+    migrate_only: bool,
 }
 
 fn handle_request(r: Request, mut ctx: Context) -> ResultV {
-    let headers: String = r.headers().iter().map(|h| -> String {
-        h.to_string()
-    }).collect::<Vec<String>>().join("\\r\\n");
-    info!("New Request [qid={}]: method: {}; url: {}; headers='{}'", ctx.qid, r.method(), r.url(), headers);
+    let headers: String = r.headers().iter().map(loggable_header).collect::<Vec<String>>().join("\\r\\n");
+    info!("New Request [qid={}]: method: {}; url: {}; headers='{}'", ctx.qid, r.method(), loggable_url(r.url()), headers);
 
     match (r.method(), r.url()) {
-        (Method::Post, "/add") => {
+        (Method::Post, url) if url == "/add" || url.starts_with("/add?") => {
             handle_method_add(r, &mut ctx)
         }
-        (Method::Get, url) if url.starts_with("/get/") => {
+        (Method::Get | Method::Post, url) if url.starts_with("/get/") => {
             handle_method_get(r, &mut ctx)
         }
+        (Method::Get, url) if url.starts_with("/info/") => {
+            handle_method_info(r, &mut ctx)
+        }
+        (Method::Delete, url) if url.starts_with("/secret/") => {
+            handle_method_delete(r, &mut ctx)
+        }
+        (Method::Get, "/metrics") => {
+            handle_method_metrics(r, &mut ctx)
+        }
+        (Method::Get, "/healthz") => {
+            handle_method_healthz(r, &mut ctx)
+        }
+        (Method::Get, "/readyz") => {
+            handle_method_readyz(r, &mut ctx)
+        }
         (_, _) => {
-            ctx.resp.set_status("Method is not implemented".to_string());
-            respond(r, &mut ctx, HTTP_501)
+            respond_error(r, &mut ctx, Error::NotImplemented)
         }
     }
 }
@@ -56,33 +90,44 @@ fn main() -> ResultV {
     let cfg = Config::load(&args.config_fn);
     logger::init_logger(&cfg)?;
 
-    let reporter = logger::get_reporter(MODULE, "Main", "");
-
-    let mut db = DB::new(&cfg.database.kind, &cfg.database.url).map_err(&reporter)?;
+    cfg.security.check()?;
+    if cfg.auth.enabled && cfg.auth.keys.is_empty() {
+        warn!("[{}] `auth.enabled` is set but no API keys are configured, nobody can add secrets", MODULE);
+    }
+    cfg.rate_limit.check()?;
+    cfg.policy.check()?;
+    cfg.ids.check()?;
+    if cfg.ids.entropy_bits() < MIN_ID_ENTROPY_BITS {
+        warn!("[{}] Link ids have only {:.0} bits of entropy, consider longer `ids.length` or larger `ids.alphabet`", MODULE, cfg.ids.entropy_bits());
+    }
+    let mut db = DB::new(&cfg)?;
     info!("[{}] Use `{}` as database backend", MODULE, db.get_kind());
 
-    db.prepare().map_err(&reporter)?;
-
-    let addr = format!("{}:{}", cfg.server.host, cfg.server.port);
-    let server = Server::http(&addr).map_err(|e| {
-        error!("[{}] Could not start server at {}: {}", MODULE, addr, e);
-        "init server error"
-    })?;
+    db.prepare()?;
+    if args.migrate_only {
+        info!("[{}] Database schema is up to date", MODULE);
+        return Ok(());
+    }
 
-    info!("[{}] Staring onetimer service at {}", MODULE, addr);
+    info!("[{}] Staring onetimer service at {}:{}", MODULE, cfg.server.host, cfg.server.port);
     info!("[{}] Config loaded from {}", MODULE, args.config_fn);
 
     let pool = threadpool::ThreadPool::new(cfg.server.workers);
 
     let db_arc = Arc::new(Mutex::new(db));
+    if cfg.sweeper.enabled && cfg.sweeper.interval > 0 {
+        sweeper::start(db_arc.clone(), cfg.sweeper.interval);
+    }
+
+    let limiter_arc = Arc::new(RateLimiter::new(&cfg.rate_limit));
     let cfg_arc = Arc::new(cfg);
-    for r in server.incoming_requests() {
+    server::serve(&cfg_arc.server, |r| {
         let db_ = db_arc.clone();
         let cfg_ = cfg_arc.clone();
+        let limiter_ = limiter_arc.clone();
         pool.execute(move || {
-            handle_request(r, Context::new(db_, cfg_)).ok();
+            handle_request(r, Context::new(db_, cfg_, limiter_)).ok();
         })
-    };
-    Ok(())
+    })
 }
 
@@ -0,0 +1,146 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+
+/// Service wide metrics, exposed by /metrics in Prometheus text format
+pub static METRICS: Metrics = Metrics::new();
+
+const ROUTES: [&str; 8] = ["add", "get", "info", "secret", "metrics", "healthz", "readyz", "other"];
+const BUCKETS_MS: [f32; 11] = [5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0];
+
+pub enum Event {
+    Created,
+    Retrieved,
+    Expired,
+    Exhausted,
+    NotFound,
+    Error,
+}
+
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS_MS.len()],
+    sum_us: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS_MS.len()],
+            sum_us: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, ms: f32) {
+        // Buckets are cumulative, so every bucket with bound above the value is incremented
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS_MS) {
+            if ms <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_us.fetch_add((ms * 1000.0) as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub struct Metrics {
+    created: AtomicU64,
+    retrieved: AtomicU64,
+    expired: AtomicU64,
+    exhausted: AtomicU64,
+    not_found: AtomicU64,
+    errors: AtomicU64,
+    latency: [Histogram; ROUTES.len()],
+    /// Counting records is expensive for some engines, so it is done by sweeper and not on every scrape
+    live: Mutex<Option<usize>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            created: AtomicU64::new(0),
+            retrieved: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            exhausted: AtomicU64::new(0),
+            not_found: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            latency: [const { Histogram::new() }; ROUTES.len()],
+            live: Mutex::new(None),
+        }
+    }
+
+    pub fn count(&self, event: Event) {
+        self.count_n(event, 1);
+    }
+
+    pub fn count_n(&self, event: Event, n: u64) {
+        let counter = match event {
+            Event::Created   => &self.created,
+            Event::Retrieved => &self.retrieved,
+            Event::Expired   => &self.expired,
+            Event::Exhausted => &self.exhausted,
+            Event::NotFound  => &self.not_found,
+            Event::Error     => &self.errors,
+        };
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Record time spent to handle request to the given url
+    pub fn observe(&self, url: &str, ms: f32) {
+        let route = url.split(['/', '?']).nth(1).unwrap_or_default();
+        let idx = ROUTES.iter().position(|r| *r == route).unwrap_or(ROUTES.len() - 1);
+        self.latency[idx].observe(ms);
+    }
+
+    /// Set number of live records, `None` if it is unknown
+    pub fn set_live(&self, live: Option<usize>) {
+        if let Ok(mut value) = self.live.lock() {
+            *value = live;
+        }
+    }
+
+    /// Render all metrics in Prometheus text format. Number of live records is skipped if it is unknown
+    pub fn render(&self, engine: &str) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("created", "Secrets created", &self.created),
+            ("retrieved", "Secrets retrieved", &self.retrieved),
+            ("expired", "Secrets expired before they were retrieved", &self.expired),
+            ("exhausted", "Secrets without clicks left which were removed by sweeper", &self.exhausted),
+            ("not_found", "Requests for secrets which were not found", &self.not_found),
+            ("errors", "Requests failed with server error", &self.errors),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP onetimer_secrets_{}_total {}", name, help);
+            let _ = writeln!(out, "# TYPE onetimer_secrets_{}_total counter", name);
+            let _ = writeln!(out, "onetimer_secrets_{}_total{{engine=\"{}\"}} {}", name, engine, value.load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(out, "# HELP onetimer_request_duration_seconds Time spent to handle request");
+        let _ = writeln!(out, "# TYPE onetimer_request_duration_seconds histogram");
+        for (route, h) in ROUTES.iter().zip(&self.latency) {
+            for (bucket, bound) in h.buckets.iter().zip(BUCKETS_MS) {
+                let _ = writeln!(
+                    out, "onetimer_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route, bound / 1000.0, bucket.load(Ordering::Relaxed)
+                );
+            }
+            let count = h.count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "onetimer_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}", route, count);
+            let _ = writeln!(
+                out, "onetimer_request_duration_seconds_sum{{route=\"{}\"}} {}",
+                route, h.sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0
+            );
+            let _ = writeln!(out, "onetimer_request_duration_seconds_count{{route=\"{}\"}} {}", route, count);
+        }
+
+        if let Some(live) = self.live.lock().ok().and_then(|live| *live) {
+            let _ = writeln!(out, "# HELP onetimer_records Records which are neither expired nor exhausted");
+            let _ = writeln!(out, "# TYPE onetimer_records gauge");
+            let _ = writeln!(out, "onetimer_records{{engine=\"{}\"}} {}", engine, live);
+        }
+        out
+    }
+}
@@ -0,0 +1,201 @@
+use crate::error::Error;
+use crate::utils::Result;
+
+
+const MODULE: &str = "MIGRATIONS";
+
+/// Schema change, which is applied to SQL database once. Migrations of every dialect are numbered from 1 without gaps,
+/// and applied ones are recorded in `schema_version` table
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+pub const SQLITE: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create msg table",
+        statements: &["CREATE TABLE IF NOT EXISTS msg (id TEXT NOT NULL, data TEXT, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL)"],
+    },
+    Migration {
+        version: 2,
+        description: "add management token",
+        statements: &["ALTER TABLE msg ADD COLUMN token TEXT NOT NULL DEFAULT ''"],
+    },
+    Migration {
+        version: 3,
+        description: "add passphrase",
+        statements: &[
+            "ALTER TABLE msg ADD COLUMN passphrase TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE msg ADD COLUMN attempts BIGINT NOT NULL DEFAULT 0",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "add owner",
+        statements: &["ALTER TABLE msg ADD COLUMN owner TEXT NOT NULL DEFAULT ''"],
+    },
+    Migration {
+        // SQLite stores blobs as is whatever the column type is
+        version: 5,
+        description: "add file name and type",
+        statements: &[
+            "ALTER TABLE msg ADD COLUMN filename TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE msg ADD COLUMN mime TEXT NOT NULL DEFAULT ''",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "add activation time",
+        statements: &["ALTER TABLE msg ADD COLUMN not_before BIGINT NOT NULL DEFAULT 0"],
+    },
+    Migration {
+        // SQLite can not alter primary key or column type, so the table is rebuilt. Duplicate ids are dropped
+        version: 7,
+        description: "add primary key and expiry index, store data as blob",
+        statements: &[
+            "CREATE TABLE msg_new (id TEXT NOT NULL PRIMARY KEY, data BLOB NOT NULL, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL, expires_at BIGINT NOT NULL, token TEXT NOT NULL DEFAULT '', passphrase TEXT NOT NULL DEFAULT '', attempts BIGINT NOT NULL DEFAULT 0, owner TEXT NOT NULL DEFAULT '', filename TEXT NOT NULL DEFAULT '', mime TEXT NOT NULL DEFAULT '', not_before BIGINT NOT NULL DEFAULT 0)",
+            "INSERT OR IGNORE INTO msg_new (id, data, max_clicks, created, lifetime, expires_at, token, passphrase, attempts, owner, filename, mime, not_before) SELECT id, CAST(COALESCE(data, '') AS BLOB), max_clicks, created, lifetime, created + lifetime, token, passphrase, attempts, owner, filename, mime, not_before FROM msg",
+            "DROP TABLE msg",
+            "ALTER TABLE msg_new RENAME TO msg",
+            "CREATE INDEX msg_expires_at ON msg (expires_at)",
+        ],
+    },
+];
+
+pub const MYSQL: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create msg table",
+        statements: &["CREATE TABLE IF NOT EXISTS msg (id TEXT NOT NULL, data TEXT, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL)"],
+    },
+    Migration {
+        version: 2,
+        description: "add management token",
+        statements: &["ALTER TABLE msg ADD COLUMN token VARCHAR(64) NOT NULL DEFAULT ''"],
+    },
+    Migration {
+        version: 3,
+        description: "add passphrase",
+        statements: &[
+            "ALTER TABLE msg ADD COLUMN passphrase VARCHAR(255) NOT NULL DEFAULT ''",
+            "ALTER TABLE msg ADD COLUMN attempts BIGINT NOT NULL DEFAULT 0",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "add owner",
+        statements: &["ALTER TABLE msg ADD COLUMN owner VARCHAR(255) NOT NULL DEFAULT ''"],
+    },
+    Migration {
+        version: 5,
+        description: "store data as binary, add file name and type",
+        statements: &[
+            "UPDATE msg SET data = '' WHERE data IS NULL",
+            "ALTER TABLE msg MODIFY data LONGBLOB NOT NULL",
+            "ALTER TABLE msg ADD COLUMN filename VARCHAR(255) NOT NULL DEFAULT ''",
+            "ALTER TABLE msg ADD COLUMN mime VARCHAR(255) NOT NULL DEFAULT ''",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "add activation time",
+        statements: &["ALTER TABLE msg ADD COLUMN not_before BIGINT NOT NULL DEFAULT 0"],
+    },
+    Migration {
+        // TEXT column can not be a primary key, so the table is rebuilt. Only the latest of the records with
+        // duplicate ids is copied, and plain INSERT fails instead of truncating values which do not fit new columns
+        version: 7,
+        description: "add primary key and expiry index",
+        statements: &[
+            "CREATE TABLE msg_new (id VARCHAR(64) NOT NULL, data LONGBLOB NOT NULL, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL, expires_at BIGINT NOT NULL, token VARCHAR(64) NOT NULL DEFAULT '', passphrase VARCHAR(255) NOT NULL DEFAULT '', attempts BIGINT NOT NULL DEFAULT 0, owner VARCHAR(255) NOT NULL DEFAULT '', filename VARCHAR(255) NOT NULL DEFAULT '', mime VARCHAR(255) NOT NULL DEFAULT '', not_before BIGINT NOT NULL DEFAULT 0, PRIMARY KEY (id), INDEX msg_expires_at (expires_at))",
+            "INSERT INTO msg_new (id, data, max_clicks, created, lifetime, expires_at, token, passphrase, attempts, owner, filename, mime, not_before) SELECT id, data, max_clicks, created, lifetime, created + lifetime, token, passphrase, attempts, owner, filename, mime, not_before FROM (SELECT msg.*, ROW_NUMBER() OVER (PARTITION BY id ORDER BY created DESC) AS row_num FROM msg) numbered WHERE row_num = 1",
+            "DROP TABLE msg",
+            "RENAME TABLE msg_new TO msg",
+        ],
+    },
+];
+
+pub const PGSQL: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create msg table",
+        statements: &["CREATE TABLE IF NOT EXISTS msg (id TEXT NOT NULL, data TEXT, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL)"],
+    },
+    Migration {
+        version: 2,
+        description: "add management token",
+        statements: &["ALTER TABLE msg ADD COLUMN token TEXT NOT NULL DEFAULT ''"],
+    },
+    Migration {
+        version: 3,
+        description: "add passphrase",
+        statements: &[
+            "ALTER TABLE msg ADD COLUMN passphrase TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE msg ADD COLUMN attempts BIGINT NOT NULL DEFAULT 0",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "add owner",
+        statements: &["ALTER TABLE msg ADD COLUMN owner TEXT NOT NULL DEFAULT ''"],
+    },
+    Migration {
+        version: 5,
+        description: "store data as binary, add file name and type",
+        statements: &[
+            "ALTER TABLE msg ALTER COLUMN data TYPE BYTEA USING convert_to(COALESCE(data, ''), 'UTF8')",
+            "ALTER TABLE msg ALTER COLUMN data SET NOT NULL",
+            "ALTER TABLE msg ADD COLUMN filename TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE msg ADD COLUMN mime TEXT NOT NULL DEFAULT ''",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "add activation time",
+        statements: &["ALTER TABLE msg ADD COLUMN not_before BIGINT NOT NULL DEFAULT 0"],
+    },
+    Migration {
+        // Only the latest of the records with duplicate ids is kept, physical position breaks ties of creation time
+        version: 7,
+        description: "add primary key and expiry index",
+        statements: &[
+            "DELETE FROM msg a USING msg b WHERE a.id = b.id AND (a.created, a.ctid) < (b.created, b.ctid)",
+            "ALTER TABLE msg ADD PRIMARY KEY (id)",
+            "ALTER TABLE msg ADD COLUMN expires_at BIGINT",
+            "UPDATE msg SET expires_at = created + lifetime",
+            "ALTER TABLE msg ALTER COLUMN expires_at SET NOT NULL",
+            "CREATE INDEX msg_expires_at ON msg (expires_at)",
+        ],
+    },
+];
+
+
+/// Schema version of database created by a version without migrations, 0 if the table does not exist.
+/// Such versions created `msg` table as migration 1 does and never changed it
+pub fn unversioned(has_table: bool) -> u32 {
+    if !has_table {
+        return 0;
+    }
+    info!("[{}] Found table without schema version, it matches version 1", MODULE);
+    1
+}
+
+/// Migrations which are not applied to the database yet. Database with schema newer than this binary knows
+/// is refused, since the binary may corrupt data written by the newer one
+pub fn pending(migrations: &'static [Migration], current: u32) -> Result<&'static [Migration]> {
+    let latest = migrations.len() as u32;
+    if current > latest {
+        error!("[{}] Database schema version {} is newer than supported {}", MODULE, current, latest);
+        return Err(Error::Config(format!("database schema version {} is newer than supported {}", current, latest)));
+    }
+    if current < latest {
+        info!("[{}] Database schema version is {}, migrating to {}", MODULE, current, latest);
+    }
+    Ok(&migrations[current as usize..])
+}
+
+pub fn log_applied(m: &Migration) {
+    info!("[{}] Applied migration {}: {}", MODULE, m.version, m.description);
+}
@@ -0,0 +1,71 @@
+use crate::error::Error;
+use crate::utils::Result;
+
+
+/// Single part of `multipart/form-data` body
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Get boundary from `multipart/form-data; boundary=...` content type
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params.next()?.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|p| p.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|pos| pos + from)
+}
+
+/// Split `multipart/form-data` body into parts
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let malformed = || Error::Parse("malformed multipart body".to_string());
+    let delimiter = format!("--{}", boundary);
+    let next_delimiter = format!("\r\n--{}", boundary);
+
+    let mut parts = Vec::new();
+    let mut pos = find(body, delimiter.as_bytes(), 0).ok_or_else(malformed)? + delimiter.len();
+    // Closing delimiter has `--` suffix
+    while !body[pos..].starts_with(b"--") {
+        // Delimiter line is followed by headers and an empty line, which comes right after it if part has no headers
+        if !body[pos..].starts_with(b"\r\n") {
+            return Err(malformed());
+        }
+        let headers_end = find(body, b"\r\n\r\n", pos).ok_or_else(malformed)?;
+        let headers = body.get(pos + 2..headers_end).unwrap_or_default();
+        // Data starts after the empty line, so delimiter can not be searched for inside that line
+        let data_start = headers_end + 4;
+        let data_end = find(body, next_delimiter.as_bytes(), data_start).ok_or_else(malformed)?;
+
+        let mut part = Part { name: String::new(), filename: None, content_type: None, data: Vec::new() };
+        for line in String::from_utf8_lossy(headers).split("\r\n") {
+            let Some((name, value)) = line.split_once(':') else { continue };
+            if name.trim().eq_ignore_ascii_case("Content-Disposition") {
+                for (param, value) in value.split(';').filter_map(|p| p.trim().split_once('=')) {
+                    let value = value.trim_matches('"').to_string();
+                    match param {
+                        "name" => part.name = value,
+                        "filename" => part.filename = Some(value),
+                        _ => {},
+                    }
+                }
+            } else if name.trim().eq_ignore_ascii_case("Content-Type") {
+                part.content_type = Some(value.trim().to_string());
+            }
+        }
+        part.data = body[data_start..data_end].to_vec();
+        parts.push(part);
+
+        pos = data_end + next_delimiter.len();
+    }
+    Ok(parts)
+}
@@ -0,0 +1,60 @@
+use crate::utils::format_timestamp;
+
+
+const PAGE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="robots" content="noindex, nofollow">
+<title>onetimer</title>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#;
+
+
+fn page(body: &str) -> String {
+    PAGE_TEMPLATE.replace("{body}", body)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Landing page for one-time link. Secret is revealed only after the button is pressed,
+/// so bots which follow links automatically do not waste a click
+pub fn reveal(with_passphrase: bool, error: Option<&str>) -> String {
+    let mut body = String::from(
+        "<p>Someone has shared a secret with you. It can be viewed a limited number of times.</p>\n"
+    );
+    if let Some(e) = error {
+        body += &format!("<p>{}</p>\n", escape(e));
+    }
+    body += "<form method=\"post\">\n";
+    if with_passphrase {
+        body += "<input type=\"password\" name=\"passphrase\" placeholder=\"Passphrase\" required>\n";
+    }
+    body += "<button type=\"submit\">Reveal secret</button>\n</form>";
+    page(&body)
+}
+
+/// Page with revealed secret data
+pub fn secret(msg: &str) -> String {
+    page(&format!("<pre>{}</pre>", escape(msg)))
+}
+
+/// Page with error description
+pub fn error(status: &str) -> String {
+    page(&format!("<p>{}</p>", escape(status)))
+}
+
+/// Page for secret which can not be revealed yet, tells when to come back
+pub fn not_yet_available(status: &str, not_before: i64) -> String {
+    page(&format!("<p>{}</p>\n<p>It can be revealed after {}.</p>", escape(status), format_timestamp(not_before)))
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use openssl::pkey::PKey;
+use openssl::ssl::{SslAcceptor, SslMethod, SslStream};
+use openssl::x509::X509;
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use tiny_http::{Request, Server};
+
+use crate::config;
+use crate::error::Error;
+use crate::logger::get_reporter;
+use crate::utils::{Result, ResultV};
+
+
+const MODULE: &str = "SERVER";
+
+/// Client has to finish TLS handshake in this time, so stalled connections do not hold their threads forever
+const HANDSHAKE_TIMEOUT_S: u64 = 10;
+/// Reading side of TLS connection gives it up this often, so response can be written in between
+const RELAY_POLL_MS: u64 = 5;
+const RELAY_BUFFER_SIZE: usize = 16384;
+
+/// Addresses of TLS clients by local addresses of their relayed connections to HTTP server
+static CLIENTS: Mutex<BTreeMap<SocketAddr, SocketAddr>> = Mutex::new(BTreeMap::new());
+
+type Acceptor = Arc<RwLock<SslAcceptor>>;
+
+
+/// Start HTTP (or HTTPS if `server.tls` is set) server and pass every incoming request to the handler.
+/// With TLS enabled certificates are reloaded on SIGHUP
+pub fn serve<F: FnMut(Request)>(cfg: &config::Server, mut handle: F) -> ResultV {
+    let addr = format!("{}:{}", cfg.host, cfg.port);
+    let server = match &cfg.tls {
+        None => Server::http(&addr).map_err(get_reporter(MODULE, "Server", |_| Error::Internal("init server error")))?,
+        Some(tls) => {
+            // TLS context of tiny_http can not be replaced, so TLS is terminated here by the only acceptor,
+            // which lives as long as the service does, and plain HTTP is relayed to tiny_http on loopback
+            let acceptor: Acceptor = Arc::new(RwLock::new(load_acceptor(tls)?));
+            let listener = TcpListener::bind(&addr).map_err(get_reporter(MODULE, "Bind", |_| Error::Internal("init server error")))?;
+            let server = Server::http("127.0.0.1:0").map_err(get_reporter(MODULE, "Server", |_| Error::Internal("init server error")))?;
+            let upstream = server.server_addr().to_ip().ok_or(Error::Internal("init server error"))?;
+            watch_sighup(tls, acceptor.clone())?;
+            thread::spawn(move || accept_tls(listener, acceptor, upstream));
+            server
+        },
+    };
+    info!("[{}] Listening at {} ({})", MODULE, addr, if cfg.tls.is_some() {"https"} else {"http"});
+
+    for r in server.incoming_requests() {
+        handle(r);
+    }
+    Ok(())
+}
+
+/// Address of the client which has sent the request, the one behind TLS relay for HTTPS requests
+pub fn client_addr(r: &Request) -> Option<SocketAddr> {
+    let addr = *r.remote_addr()?;
+    match CLIENTS.lock() {
+        Ok(clients) => Some(clients.get(&addr).copied().unwrap_or(addr)),
+        Err(_) => Some(addr),
+    }
+}
+
+fn load_acceptor(tls: &config::Tls) -> Result<SslAcceptor> {
+    let certificate = std::fs::read(&tls.cert).map_err(get_reporter(MODULE, "Certificate", |_| Error::Config(format!("can not read `{}`", tls.cert))))?;
+    let private_key = std::fs::read(&tls.key).map_err(get_reporter(MODULE, "Private key", |_| Error::Config(format!("can not read `{}`", tls.key))))?;
+
+    let invalid = |_| Error::Config("TLS certificate or key is not valid".to_string());
+    let build = || -> std::result::Result<SslAcceptor, openssl::error::ErrorStack> {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+        // Leaf certificate comes first, the rest of the chain is sent after it
+        let mut chain = X509::stack_from_pem(&certificate)?.into_iter();
+        if let Some(leaf) = chain.next() {
+            builder.set_certificate(&leaf)?;
+        }
+        for cert in chain {
+            builder.add_extra_chain_cert(cert)?;
+        }
+        let key = PKey::private_key_from_pem(&private_key)?;
+        builder.set_private_key(&key)?;
+        builder.check_private_key()?;
+        Ok(builder.build())
+    };
+    build().map_err(get_reporter(MODULE, "TLS", invalid))
+}
+
+fn watch_sighup(tls: &config::Tls, acceptor: Acceptor) -> ResultV {
+    let tls = tls.clone();
+    let mut signals = Signals::new([SIGHUP]).map_err(get_reporter(MODULE, "Signals", |_| Error::Internal("signal error")))?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("[{}] SIGHUP received, reloading TLS certificates", MODULE);
+            // Connections which are already accepted keep the old context, new ones get the new one
+            match (load_acceptor(&tls), acceptor.write()) {
+                (Ok(loaded), Ok(mut current)) => *current = loaded,
+                (Err(_), _) => error!("[{}] Failed to reload certificates, keep using the old ones", MODULE),
+                (_, Err(e)) => error!("[{}] Acceptor lock error: {}", MODULE, e),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn accept_tls(listener: TcpListener, acceptor: Acceptor, upstream: SocketAddr) {
+    for stream in listener.incoming() {
+        let client = match stream {
+            Ok(client) => client,
+            Err(e) => {
+                error!("[{}] Failed to accept connection: {}", MODULE, e);
+                continue;
+            },
+        };
+        let acceptor = match acceptor.read() {
+            Ok(acceptor) => acceptor.clone(),
+            Err(e) => {
+                error!("[{}] Acceptor lock error: {}", MODULE, e);
+                continue;
+            },
+        };
+        // Handshake is done by connection thread, so a slow client does not hold up the others
+        thread::spawn(move || relay(client, acceptor, upstream));
+    }
+}
+
+/// Decrypt TLS connection and pass it to HTTP server, responses are passed back encrypted
+fn relay(client: TcpStream, acceptor: SslAcceptor, upstream: SocketAddr) {
+    let Ok(peer) = client.peer_addr() else { return };
+    client.set_read_timeout(Some(Duration::from_secs(HANDSHAKE_TIMEOUT_S))).ok();
+    // Failed handshakes are expected from scanners and clients which do not trust the certificate
+    let Ok(tls) = acceptor.accept(client) else { return };
+    let Ok(server) = TcpStream::connect(upstream) else { return };
+    let Ok(local) = server.local_addr() else { return };
+
+    // Client is registered before its first request reaches the server
+    if let Ok(mut clients) = CLIENTS.lock() {
+        clients.insert(local, peer);
+    }
+    tls.get_ref().set_read_timeout(Some(Duration::from_millis(RELAY_POLL_MS))).ok();
+    let tls = Arc::new(Mutex::new(tls));
+
+    let responses = match (server.try_clone(), tls.clone()) {
+        (Ok(from), to) => thread::spawn(move || relay_responses(from, to)),
+        (Err(_), _) => return,
+    };
+    relay_requests(tls, server);
+    // Server closes its side after the last response, so the handler is done with the client by then
+    responses.join().ok();
+
+    if let Ok(mut clients) = CLIENTS.lock() {
+        clients.remove(&local);
+    }
+}
+
+fn relay_requests(from: Arc<Mutex<SslStream<TcpStream>>>, mut to: TcpStream) {
+    let mut buf = [0u8; RELAY_BUFFER_SIZE];
+    loop {
+        let read = match from.lock() {
+            Ok(mut tls) => tls.read(&mut buf),
+            Err(_) => break,
+        };
+        match read {
+            Ok(0) => break,
+            Ok(n) => if to.write_all(&buf[..n]).is_err() { break },
+            // Lock is released by now, the pause lets waiting response writer take it
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => thread::sleep(Duration::from_millis(1)),
+            Err(_) => break,
+        }
+    }
+    to.shutdown(Shutdown::Write).ok();
+}
+
+fn relay_responses(mut from: TcpStream, to: Arc<Mutex<SslStream<TcpStream>>>) {
+    let mut buf = [0u8; RELAY_BUFFER_SIZE];
+    while let Ok(n) = from.read(&mut buf) {
+        if n == 0 || !write_tls(&to, &buf[..n]) {
+            break;
+        }
+    }
+    if let Ok(mut tls) = to.lock() {
+        tls.shutdown().ok();
+        tls.get_ref().shutdown(Shutdown::Both).ok();
+    }
+}
+
+/// Write the whole buffer to TLS connection. Socket has read timeout set, and writing may need to read
+/// (e.g. TLS 1.3 key update), so timeouts are retried
+fn write_tls(to: &Mutex<SslStream<TcpStream>>, mut buf: &[u8]) -> bool {
+    while !buf.is_empty() {
+        let written = match to.lock() {
+            Ok(mut tls) => tls.write(buf),
+            Err(_) => return false,
+        };
+        match written {
+            Ok(0) => return false,
+            Ok(n) => buf = &buf[n..],
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => thread::yield_now(),
+            Err(_) => return false,
+        }
+    }
+    true
+}
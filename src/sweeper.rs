@@ -0,0 +1,43 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::db::DB;
+use crate::error::Error;
+use crate::metrics::METRICS;
+
+
+const MODULE: &str = "SWEEPER";
+
+/// Start background thread which periodically deletes expired and exhausted records and counts the live ones.
+/// The first sweep is done at once, so the number of live records is known from the start
+pub fn start(db: Arc<Mutex<DB>>, interval: u64) -> thread::JoinHandle<()> {
+    info!("[{}] Start sweeping expired records every {} seconds", MODULE, interval);
+    thread::spawn(move || loop {
+        sweep(&db);
+        thread::sleep(Duration::from_secs(interval));
+    })
+}
+
+fn sweep(db: &Arc<Mutex<DB>>) {
+    let mut db = match db.lock() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("[{}] Database lock error: {}", MODULE, e);
+            return;
+        }
+    };
+    match db.purge() {
+        Ok(p) if p.expired == 0 && p.exhausted == 0 => debug!("[{}] No expired records found", MODULE),
+        Ok(p) => info!("[{}] Removed {} expired and {} exhausted records", MODULE, p.expired, p.exhausted),
+        Err(e) => error!("[{}] Failed to remove expired records: {}", MODULE, e),
+    }
+    match db.count() {
+        Ok(n) => METRICS.set_live(Some(n)),
+        Err(Error::NotImplemented) => METRICS.set_live(None),
+        Err(e) => {
+            error!("[{}] Failed to count records: {}", MODULE, e);
+            METRICS.set_live(None);
+        }
+    }
+}
@@ -1,23 +1,32 @@
 use rand::Rng;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
+use crate::error::Error;
 
-pub type ErrorStr = &'static str;
-pub type Result<T> = std::result::Result<T, ErrorStr>;
+
+pub type Result<T> = std::result::Result<T, Error>;
 pub type ResultV = Result<()>;
 
-const URL_ID_CHARSET: &[u8] = b"0123456789abcdef";
-pub fn generate_hex_id(length: u32) -> String {
+const HEX_CHARSET: &[u8] = b"0123456789abcdef";
+
+/// Random string of the given length, every character is picked uniformly from the charset
+pub fn generate_id(length: u32, charset: &[u8]) -> String {
     let mut rng = rand::thread_rng();
 
     (0..length).map(
         |_| {
-            let idx = rng.gen_range(0..URL_ID_CHARSET.len());
-            URL_ID_CHARSET[idx] as char
+            let idx = rng.gen_range(0..charset.len());
+            charset[idx] as char
         }
     ).collect()
 }
 
+pub fn generate_hex_id(length: u32) -> String {
+    generate_id(length, HEX_CHARSET)
+}
+
 
 fn current_duration() -> Duration {
     SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards")
@@ -31,14 +40,62 @@ pub fn now() -> i64 {
     current_duration().as_secs() as i64
 }
 
-pub fn one() -> u32 {1}
-pub fn day_seconds() -> u64 {
-    24 * 60 * 60
+pub fn is_zero(x: &i64) -> bool {
+    *x == 0
 }
-pub fn week_seconds() -> u64 {
-    7 * day_seconds()
+
+pub fn is_zero_u32(x: &u32) -> bool {
+    *x == 0
 }
 
-pub fn is_zero(x: &i64) -> bool {
+pub fn is_zero_u64(x: &u64) -> bool {
     *x == 0
 }
+
+/// Decode value from `application/x-www-form-urlencoded` string
+pub fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => { out.push(b); i += 2; },
+                    None => out.push(b'%'),
+                }
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decoded `name=value` pairs of url query string or url encoded form
+pub fn parse_query(s: &str) -> Vec<(String, String)> {
+    s.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (url_decode(name), url_decode(value)))
+        .collect()
+}
+
+/// Unix time given either as a number of seconds or as RFC 3339 date, e.g. `2024-09-01T09:00:00+03:00`
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let s = s.trim();
+    if let Ok(ts) = s.parse::<i64>() {
+        return Ok(ts);
+    }
+    OffsetDateTime::parse(s, &Rfc3339)
+        .map(|t| t.unix_timestamp())
+        .map_err(|e| Error::Parse(format!("`{}` is neither unix time nor RFC 3339 date: {}", s, e)))
+}
+
+/// Unix time as RFC 3339 date in UTC, or the number itself if it is out of range
+pub fn format_timestamp(ts: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(ts).ok()
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_else(|| ts.to_string())
+}
@@ -0,0 +1,59 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check info [$db_kind]:"
+
+# Check that info does not consume clicks
+add_resp=$(send_add "$PAYLOAD" "2" "1000")
+status=$( echo "$add_resp" | jq -r .status )
+if [ "$status" != OK ]; then
+    echo "ADD FAILED"
+    exit 1
+fi
+url=$( echo "$add_resp" | jq -r .msg )
+for expected in 2 2 1; do
+    if [ "$expected" == 1 ]; then
+        send_get "$url" > /dev/null
+    fi
+    info_resp=$(send_info "$url")
+    clicks=$( echo "$info_resp" | jq -r .clicks )
+    msg=$( echo "$info_resp" | jq -r .msg )
+    if [ "$clicks" != "$expected" ]; then
+        echo "INFO CLICKS FAILED"
+        exit 1
+    fi
+    if [ "$msg" == "$PAYLOAD" ]; then
+        echo "INFO DATA FAILED"
+        exit 1
+    fi
+done
+send_get "$url" > /dev/null
+info_resp=$(send_info "$url")
+resp_status=$( echo "$info_resp" | jq -r .status )
+if [ "$resp_status" == OK ]; then
+    echo "INFO STATUS FAILED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,62 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check delete [$db_kind]:"
+
+# Check that owner can delete the link with management token
+add_resp=$(send_add "$PAYLOAD" "2" "1000")
+status=$( echo "$add_resp" | jq -r .status )
+if [ "$status" != OK ]; then
+    echo "ADD FAILED"
+    exit 1
+fi
+url=$( echo "$add_resp" | jq -r .msg )
+token=$( echo "$add_resp" | jq -r .token )
+
+# Wrong token looks the same as missing link, so existing ids can not be found out
+del_resp=$(send_delete "$url" "wrong$token")
+if [[ $(echo "$del_resp" | jq -r .error.code) != not_found ]]; then
+    echo "DELETE WITH WRONG TOKEN FAILED"
+    exit 1
+fi
+del_resp=$(send_delete "$url" "$token")
+resp_status=$( echo "$del_resp" | jq -r .status )
+if [ "$resp_status" != OK ]; then
+    echo "DELETE FAILED"
+    exit 1
+fi
+
+get_resp=$(send_get "$url")
+msg=$( echo "$get_resp" | jq -r .msg )
+resp_status=$( echo "$get_resp" | jq -r .status )
+if [ "$resp_status" == OK ]; then
+    echo "GET STATUS FAILED"
+    exit 1
+fi
+if [ "$msg" == "$PAYLOAD" ]; then
+    echo "GET FAILED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,55 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check reveal page [$db_kind]:"
+
+# Check that opening the link in browser does not consume a click
+add_resp=$(send_add "$PAYLOAD" "1" "1000")
+status=$( echo "$add_resp" | jq -r .status )
+if [ "$status" != OK ]; then
+    echo "ADD FAILED"
+    exit 1
+fi
+url=$( echo "$add_resp" | jq -r .msg )
+for (( i = 0; i < 3; i++ )) do
+    page=$(curl -H "Accept: text/html" "$url" 2>/dev/null)
+    if [[ "$page" != *"Reveal secret"* ]] || [[ "$page" == *"$PAYLOAD"* ]]; then
+        echo "REVEAL PAGE FAILED"
+        exit 1
+    fi
+done
+
+# Press the button
+page=$(curl -X POST -H "Accept: text/html" "$url" 2>/dev/null)
+if [[ "$page" != *"$PAYLOAD"* ]]; then
+    echo "POST FAILED"
+    exit 1
+fi
+page=$(curl -X POST -H "Accept: text/html" "$url" 2>/dev/null)
+if [[ "$page" == *"$PAYLOAD"* ]]; then
+    echo "SECOND POST FAILED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,94 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check passphrase [$db_kind]:"
+
+send_get_with_passphrase() {
+    url=$1
+    passphrase=$2
+    resp=$(curl -H "Accept: application/json" -d "{\"passphrase\": \"$passphrase\"}" "$url" 2>/dev/null)
+    echo "$resp"
+}
+
+# Check that wrong passphrase does not consume a click
+add_resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"passphrase\": \"pass\"}" http://127.0.0.1:8080/add 2>/dev/null)
+status=$( echo "$add_resp" | jq -r .status )
+if [ "$status" != OK ]; then
+    echo "ADD FAILED"
+    exit 1
+fi
+url=$( echo "$add_resp" | jq -r .msg )
+get_resp=$(send_get "$url")
+resp_status=$( echo "$get_resp" | jq -r .status )
+if [ "$resp_status" == OK ]; then
+    echo "GET WITHOUT PASSPHRASE FAILED"
+    exit 1
+fi
+get_resp=$(send_get_with_passphrase "$url" "wrong")
+resp_status=$( echo "$get_resp" | jq -r .status )
+if [ "$resp_status" == OK ]; then
+    echo "GET WITH WRONG PASSPHRASE FAILED"
+    exit 1
+fi
+get_resp=$(send_get_with_passphrase "$url" "pass")
+msg=$( echo "$get_resp" | jq -r .msg )
+if [ "$msg" != "$PAYLOAD" ]; then
+    echo "GET WITH PASSPHRASE FAILED"
+    exit 1
+fi
+
+# Check that empty passphrase is not counted as an attempt
+add_resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"passphrase\": \"pass\"}" http://127.0.0.1:8080/add 2>/dev/null)
+url=$( echo "$add_resp" | jq -r .msg )
+for (( i = 0; i < 5; i++ )) do
+    send_get_with_passphrase "$url" "" > /dev/null
+done
+get_resp=$(send_get_with_passphrase "$url" "pass")
+msg=$( echo "$get_resp" | jq -r .msg )
+if [ "$msg" != "$PAYLOAD" ]; then
+    echo "EMPTY PASSPHRASE IS COUNTED AS ATTEMPT"
+    exit 1
+fi
+
+# Check that record is destroyed after too many wrong attempts
+add_resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"passphrase\": \"pass\"}" http://127.0.0.1:8080/add 2>/dev/null)
+url=$( echo "$add_resp" | jq -r .msg )
+for (( i = 0; i < 5; i++ )) do
+    send_get_with_passphrase "$url" "wrong" > /dev/null
+done
+get_resp=$(send_get_with_passphrase "$url" "pass")
+msg=$( echo "$get_resp" | jq -r .msg )
+if [ "$msg" == "$PAYLOAD" ]; then
+    echo "ATTEMPTS LIMIT FAILED"
+    exit 1
+fi
+
+# Check that zero attempts limit is refused
+sed '/^pepper/a max_passphrase_attempts = 0' "$config_fn" > config_zero_attempts.toml
+if "$ROOT_DIR/target/release/onetimer" --migrate-only config_zero_attempts.toml 2>/dev/null; then
+    echo "ZERO ATTEMPTS LIMIT IS NOT REFUSED"
+    exit 1
+fi
+echo OK
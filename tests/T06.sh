@@ -0,0 +1,89 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Enable API keys: one is given in plain text, another one as SHA-256 hash of "hashed-key"
+cat >> "$config_fn" <<EOF
+
+[auth]
+enabled = true
+keys = [
+    { id = "plain", key = "plain-key" },
+    { id = "hashed", hash = "$(echo -n hashed-key | sha256sum | cut -d' ' -f1)" },
+]
+EOF
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check auth [$db_kind]:"
+
+send_add_with_key() {
+    key=$1
+    code=$(curl -o /dev/null -w "%{http_code}" -H "Authorization: Bearer $key" -d "{\"data\": \"$PAYLOAD\"}" http://127.0.0.1:8080/add 2>/dev/null)
+    echo "$code"
+}
+
+# Check that /add is rejected without a valid key
+headers=$(curl -o /dev/null -D - -d "{\"data\": \"$PAYLOAD\"}" http://127.0.0.1:8080/add 2>/dev/null)
+if ! echo "$headers" | head -1 | grep -q " 401 "; then
+    echo "ADD WITHOUT KEY FAILED"
+    exit 1
+fi
+if ! echo "$headers" | grep -qi '^WWW-Authenticate: Bearer'; then
+    echo "WWW-AUTHENTICATE FAILED"
+    exit 1
+fi
+code=$(send_add_with_key "wrong-key")
+if [ "$code" != 401 ]; then
+    echo "ADD WITH WRONG KEY FAILED"
+    exit 1
+fi
+
+# Check that both plain and hashed keys are accepted
+code=$(send_add_with_key "plain-key")
+if [ "$code" != 200 ]; then
+    echo "ADD WITH PLAIN KEY FAILED"
+    exit 1
+fi
+
+# Check that authentication scheme is case-insensitive
+code=$(curl -o /dev/null -w "%{http_code}" -H "Authorization: bearer plain-key" -d "{\"data\": \"$PAYLOAD\"}" http://127.0.0.1:8080/add 2>/dev/null)
+if [ "$code" != 200 ]; then
+    echo "ADD WITH LOWERCASE SCHEME FAILED"
+    exit 1
+fi
+add_resp=$(curl -H "Authorization: Bearer hashed-key" -d "{\"data\": \"$PAYLOAD\"}" http://127.0.0.1:8080/add 2>/dev/null)
+status=$( echo "$add_resp" | jq -r .status )
+if [ "$status" != OK ]; then
+    echo "ADD WITH HASHED KEY FAILED"
+    exit 1
+fi
+
+# Check that /get stays public
+url=$( echo "$add_resp" | jq -r .msg )
+get_resp=$(send_get "$url")
+msg=$( echo "$get_resp" | jq -r .msg )
+if [ "$msg" != "$PAYLOAD" ]; then
+    echo "GET FAILED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,107 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Small limits, so they are easy to hit
+cat >> "$config_fn" <<EOF
+
+[rate_limit]
+enabled = true
+burst = 5
+rate = 0.1
+max_not_found = 2
+ban_time = 60
+EOF
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check rate limit [$db_kind]:"
+
+get_code() {
+    url=$1
+    code=$(curl -o /dev/null -w "%{http_code}" -H "Accept: application/json" "$url" 2>/dev/null)
+    echo "$code"
+}
+
+# Check that requests over the burst are rejected
+for (( i = 0; i < 5; i++ )) do
+    add_resp=$(send_add "$PAYLOAD" 1 60)
+done
+status=$( echo "$add_resp" | jq -r .status )
+if [ "$status" != OK ]; then
+    echo "ADD WITHIN BURST FAILED"
+    exit 1
+fi
+code=$(curl -o /dev/null -w "%{http_code}" -d "{\"data\": \"$PAYLOAD\"}" http://127.0.0.1:8080/add 2>/dev/null)
+if [ "$code" != 429 ]; then
+    echo "ADD OVER BURST FAILED"
+    exit 1
+fi
+kill $pid
+wait $pid || true
+
+# Check that client is banned after too many links not found
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+for (( i = 0; i < 2; i++ )) do
+    code=$(get_code "${url%/*}/0000")
+    if [ "$code" != 404 ]; then
+        echo "GET WRONG LINK FAILED"
+        exit 1
+    fi
+done
+code=$(get_code "$url")
+if [ "$code" != 429 ]; then
+    echo "BAN FAILED"
+    exit 1
+fi
+
+kill $pid
+wait $pid || true
+
+# Check that guessing ids with DELETE leads to the ban as well
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+for (( i = 0; i < 2; i++ )) do
+    code=$(curl -o /dev/null -w "%{http_code}" -X DELETE -H "X-Management-Token: token" "http://127.0.0.1:8080/secret/0000$i" 2>/dev/null)
+    if [ "$code" != 404 ]; then
+        echo "DELETE WRONG LINK FAILED"
+        exit 1
+    fi
+done
+code=$(get_code "$url")
+if [ "$code" != 429 ]; then
+    echo "BAN AFTER DELETE FAILED"
+    exit 1
+fi
+
+# Check that bucket which never refills is refused
+sed -i 's/^rate = 0.1/rate = 0.0/' "$config_fn"
+if "$ROOT_DIR/target/release/onetimer" --migrate-only "$config_fn" 2>/dev/null; then
+    echo "ZERO RATE IS NOT REFUSED"
+    exit 1
+fi
+echo OK
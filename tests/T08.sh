@@ -0,0 +1,73 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Number of live records is counted by sweeper
+cat >> "$config_fn" <<EOF
+
+[sweeper]
+enabled = true
+interval = 1
+EOF
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check metrics [$db_kind]:"
+
+metric() {
+    name=$1
+    value=$(curl http://127.0.0.1:8080/metrics 2>/dev/null | grep "^$name" | awk '{print $2}')
+    echo "$value"
+}
+
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+send_add "$PAYLOAD" 1 60 > /dev/null
+send_get "$url" > /dev/null
+send_get "$url" > /dev/null
+sleep 2
+
+if [ "$(metric "onetimer_secrets_created_total{engine=\"$db_kind\"}")" != 2 ]; then
+    echo "CREATED COUNTER FAILED"
+    exit 1
+fi
+if [ "$(metric "onetimer_secrets_retrieved_total{engine=\"$db_kind\"}")" != 1 ]; then
+    echo "RETRIEVED COUNTER FAILED"
+    exit 1
+fi
+if [ "$(metric "onetimer_secrets_not_found_total{engine=\"$db_kind\"}")" != 1 ]; then
+    echo "NOT FOUND COUNTER FAILED"
+    exit 1
+fi
+# Redis keyspace may be shared, so its records are not counted
+records=1
+if [ "$db_kind" == redis ]; then
+    records=""
+fi
+if [ "$(metric "onetimer_records{engine=\"$db_kind\"}")" != "$records" ]; then
+    echo "RECORDS GAUGE FAILED"
+    exit 1
+fi
+if [ "$(metric 'onetimer_request_duration_seconds_count{route="get"}')" != 2 ]; then
+    echo "LATENCY HISTOGRAM FAILED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,50 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check health probes [$db_kind]:"
+
+get_code() {
+    code=$(curl -o /dev/null -w "%{http_code}" "http://127.0.0.1:8080$1" 2>/dev/null)
+    echo "$code"
+}
+
+if [ "$(get_code /healthz)" != 200 ]; then
+    echo "HEALTHZ FAILED"
+    exit 1
+fi
+if [ "$(get_code /readyz)" != 200 ]; then
+    echo "READYZ FAILED"
+    exit 1
+fi
+
+# Check that readiness fails when backend is gone
+if [ "$db_kind" == file ]; then
+    rm -rf ./db
+    if [ "$(get_code /readyz)" != 503 ]; then
+        echo "READYZ WITHOUT BACKEND FAILED"
+        exit 1
+    fi
+fi
+echo OK
@@ -0,0 +1,56 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check error codes [$db_kind]:"
+
+check_error() {
+    resp=$1
+    expected=$2
+    code=$( echo "$resp" | jq -r .error.code )
+    request_id=$( echo "$resp" | jq -r .request_id )
+    status=$( echo "$resp" | jq -r .status )
+    if [ "$code" != "$expected" ] || [ "$request_id" == null ] || [ "$status" == OK ]; then
+        echo "ERROR CODE $expected FAILED"
+        exit 1
+    fi
+}
+
+check_error "$(curl -d "not json" http://127.0.0.1:8080/add 2>/dev/null)" invalid_request
+check_error "$(send_get "http://127.0.0.1:8080/get/0000/0000")" not_found
+check_error "$(curl -X PUT http://127.0.0.1:8080/add 2>/dev/null)" not_implemented
+
+# Check that error page refers to the request as well
+if ! curl -s -D - -o /dev/null -X POST -H "Accept: text/html" http://127.0.0.1:8080/get/0000/0000 | grep -qi "^X-Request-Id: [0-9a-f]"; then
+    echo "REQUEST ID HEADER FAILED"
+    exit 1
+fi
+
+# Check that successful response has no error fields
+add_resp=$(send_add "$PAYLOAD" 1 60)
+if [ "$( echo "$add_resp" | jq -r '.error, .request_id' | sort -u )" != null ]; then
+    echo "SUCCESS RESPONSE FAILED"
+    exit 1
+fi
+echo OK
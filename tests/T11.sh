@@ -0,0 +1,65 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check content negotiation [$db_kind]:"
+
+content_type() {
+    curl -o /dev/null -w "%{content_type}" "$@" 2>/dev/null
+}
+
+url=$( send_add "$PAYLOAD" 3 60 | jq -r .msg )
+if [ "$(content_type -d "{}" http://127.0.0.1:8080/add)" != application/json ]; then
+    echo "JSON CONTENT TYPE FAILED"
+    exit 1
+fi
+resp=$(curl -H "Accept: text/plain" "$url" 2>/dev/null)
+if [ "$resp" != "$PAYLOAD" ]; then
+    echo "RAW TEXT FAILED"
+    exit 1
+fi
+if [[ "$(content_type -H "Accept: text/html" -d "" "$url")" != text/html* ]]; then
+    echo "HTML FAILED"
+    exit 1
+fi
+msg=$(curl -H "Accept: text/html;q=0.5, application/json" -d "" "$url" 2>/dev/null | jq -r .msg)
+if [ "$msg" != "$PAYLOAD" ]; then
+    echo "QUALITY FAILED"
+    exit 1
+fi
+
+# Check that GET shows the landing page without using a click unless client prefers JSON or raw text
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+for accept in "text/html, text/plain;q=0.5" "text/plain;q=0" "*/*"; do
+    if [[ "$(content_type -H "Accept: $accept" "$url")" != text/html* ]]; then
+        echo "LANDING PAGE FAILED FOR $accept"
+        exit 1
+    fi
+done
+msg=$(curl -H "Accept: application/json, text/plain, */*" "$url" 2>/dev/null | jq -r .msg)
+if [ "$msg" != "$PAYLOAD" ]; then
+    echo "GET AFTER LANDING PAGE FAILED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,100 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check file upload [$db_kind]:"
+
+head -c 4096 /dev/urandom > ./upload.bin
+
+# Check multipart upload
+url=$(curl -F "file=@./upload.bin;type=application/x-binary" http://127.0.0.1:8080/add 2>/dev/null | jq -r .msg)
+headers=$(curl -D - -o ./download.bin -H "Accept: application/json" "$url" 2>/dev/null)
+if ! cmp -s ./upload.bin ./download.bin; then
+    echo "MULTIPART UPLOAD FAILED"
+    exit 1
+fi
+if ! echo "$headers" | grep -q 'Content-Disposition: attachment; filename="upload.bin"'; then
+    echo "CONTENT DISPOSITION FAILED"
+    exit 1
+fi
+if ! echo "$headers" | grep -q 'Content-Type: application/x-binary'; then
+    echo "CONTENT TYPE FAILED"
+    exit 1
+fi
+if ! echo "$headers" | grep -qi 'X-Content-Type-Options: nosniff'; then
+    echo "NOSNIFF FAILED"
+    exit 1
+fi
+
+# Check that part without headers does not break parsing
+printf -- '--b\r\nContent-Disposition: form-data; name="data"\r\n\r\n%s\r\n--b\r\n\r\nignored\r\n--b--\r\n' "$PAYLOAD" > ./body.txt
+url=$(curl --data-binary @./body.txt -H "Content-Type: multipart/form-data; boundary=b" http://127.0.0.1:8080/add 2>/dev/null | jq -r .msg)
+if [[ $(send_get "$url" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "PART WITHOUT HEADERS FAILED"
+    exit 1
+fi
+
+# Check that closing delimiter right after the empty line is rejected as malformed body
+printf -- '--B\r\n\r\n--B--' > ./body.txt
+code=$(curl -o /dev/null -w "%{http_code}" --data-binary @./body.txt -H "Content-Type: multipart/form-data; boundary=B" http://127.0.0.1:8080/add 2>/dev/null)
+if [ "$code" != 400 ]; then
+    echo "MALFORMED MULTIPART FAILED: $code"
+    exit 1
+fi
+
+# Check that file name and MIME type longer than database columns are rejected
+long_name=$(printf 'a%.0s' {1..256})
+code=$(curl -o /dev/null -w "%{http_code}" -F "file=@./upload.bin;filename=$long_name" http://127.0.0.1:8080/add 2>/dev/null)
+if [ "$code" != 400 ]; then
+    echo "LONG FILE NAME FAILED: $code"
+    exit 1
+fi
+code=$(curl -o /dev/null -w "%{http_code}" -F "file=@./upload.bin;type=application/$long_name" http://127.0.0.1:8080/add 2>/dev/null)
+if [ "$code" != 400 ]; then
+    echo "LONG MIME TYPE FAILED: $code"
+    exit 1
+fi
+code=$(curl -o /dev/null -w "%{http_code}" --data-binary @./upload.bin -H "Content-Type: application/octet-stream" "http://127.0.0.1:8080/add?filename=$long_name" 2>/dev/null)
+if [ "$code" != 400 ]; then
+    echo "LONG FILE NAME IN URL FAILED: $code"
+    exit 1
+fi
+
+# Check raw body upload
+url=$(curl --data-binary @./upload.bin -H "Content-Type: application/octet-stream" "http://127.0.0.1:8080/add?filename=key.bin&max_clicks=2" 2>/dev/null | jq -r .msg)
+for (( i = 0; i < 2; i++ )) do
+    rm -f ./download.bin
+    curl -o ./download.bin -H "Accept: application/json" "$url" 2>/dev/null
+    if ! cmp -s ./upload.bin ./download.bin; then
+        echo "OCTET STREAM UPLOAD FAILED"
+        exit 1
+    fi
+done
+
+# Check that query of /add is not written to logs
+if grep -q "filename=key.bin" ./onetimer.log; then
+    echo "ADD QUERY IS LOGGED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,86 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Small limits, so they are easy to hit
+cat >> "$config_fn" <<EOF
+
+[server]
+max_body_bytes = 1024
+max_secret_bytes = 512
+EOF
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check payload limits [$db_kind]:"
+
+get_code() {
+    curl -o /dev/null -w "%{http_code}" "$@" http://127.0.0.1:8080/add 2>/dev/null
+}
+
+head -c 256 /dev/urandom > ./small.bin
+head -c 768 /dev/urandom > ./medium.bin
+head -c 65536 /dev/urandom > ./large.bin
+
+# Check secret within limits
+code=$(get_code --data-binary @./small.bin -H "Content-Type: application/octet-stream")
+if [[ "$code" != "200" ]]; then
+    echo "SMALL SECRET FAILED: $code"
+    exit 1
+fi
+
+# Check secret over max_secret_bytes, but body within max_body_bytes
+code=$(get_code --data-binary @./medium.bin -H "Content-Type: application/octet-stream")
+if [[ "$code" != "413" ]]; then
+    echo "MAX SECRET BYTES FAILED: $code"
+    exit 1
+fi
+
+# Check body over max_body_bytes with declared length
+resp=$(curl --data-binary @./large.bin -H "Content-Type: application/octet-stream" http://127.0.0.1:8080/add 2>/dev/null)
+if [[ $(echo "$resp" | jq -r .error.code) != "payload_too_large" ]]; then
+    echo "MAX BODY BYTES FAILED: $resp"
+    exit 1
+fi
+
+# Check chunked body over max_body_bytes
+code=$(get_code --data-binary @./large.bin -H "Content-Type: application/octet-stream" -H "Transfer-Encoding: chunked")
+if [[ "$code" != "413" ]]; then
+    echo "CHUNKED BODY FAILED: $code"
+    exit 1
+fi
+
+# Check JSON secret over max_secret_bytes
+code=$(get_code -d "{\"data\": \"$(head -c 600 /dev/zero | tr '\0' 'a')\"}")
+if [[ "$code" != "413" ]]; then
+    echo "JSON SECRET FAILED: $code"
+    exit 1
+fi
+
+# Service keeps working after rejected requests
+js=$(send_add "$PAYLOAD" 1 60)
+resp=$(send_get "$(echo "$js" | jq -r .msg)")
+if [[ $(echo "$resp" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "GET AFTER LIMITS FAILED: $resp"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,72 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+cat >> "$config_fn" <<EOF
+
+[policy]
+mode = "clamp"
+min_lifetime = 10
+max_lifetime = 100
+default_lifetime = 50
+max_clicks = 5
+default_max_clicks = 2
+EOF
+
+start_service() {
+    "$ROOT_DIR/target/release/onetimer" "$config_fn" &
+    pid=$!
+    sleep 2
+}
+
+# Run service
+start_service
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check lifetime and clicks policy [$db_kind]:"
+
+check_add() {
+    msg=$1
+    expected=$2
+    resp=$(curl -d "$msg" http://127.0.0.1:8080/add 2>/dev/null)
+    actual=$(echo "$resp" | jq -c '[.lifetime, .clicks, .error.code]')
+    if [[ "$actual" != "$expected" ]]; then
+        echo "POLICY FAILED: $msg => $resp"
+        exit 1
+    fi
+}
+
+# Check defaults and clamping
+check_add '{"data": "x"}' '[50,2,null]'
+check_add '{"data": "x", "lifetime": 1, "max_clicks": 1}' '[10,1,null]'
+check_add '{"data": "x", "lifetime": 100000, "max_clicks": 1000}' '[100,5,null]'
+
+# Check rejection
+kill $pid
+wait $pid || true
+sed -i 's/mode = "clamp"/mode = "reject"/' "$config_fn"
+start_service
+
+check_add '{"data": "x", "lifetime": 20, "max_clicks": 3}' '[20,3,null]'
+check_add '{"data": "x", "lifetime": 5}' '[null,null,"policy_violation"]'
+check_add '{"data": "x", "lifetime": 100000}' '[null,null,"policy_violation"]'
+check_add '{"data": "x", "max_clicks": 6}' '[null,null,"policy_violation"]'
+check_add '{"data": "x", "lifetime": 0}' '[null,null,"policy_violation"]'
+check_add '{"data": "x", "max_clicks": 0}' '[null,null,"policy_violation"]'
+check_add '{"data": "x"}' '[50,2,null]'
+echo OK
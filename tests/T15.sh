@@ -0,0 +1,92 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check expires_at and not_before [$db_kind]:"
+
+# Check absolute expiry time in RFC 3339 format
+expires_at=$(( $(date +%s) + 3 ))
+add_resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"expires_at\": \"$(date -u -d "@$expires_at" +%Y-%m-%dT%H:%M:%SZ)\"}" http://127.0.0.1:8080/add 2>/dev/null)
+if [[ $(echo "$add_resp" | jq -r .expired) != "$expires_at" ]]; then
+    echo "EXPIRES AT FAILED: $add_resp"
+    exit 1
+fi
+expiring_url=$(echo "$add_resp" | jq -r .msg)
+
+# Check that lifetime and expires_at can not be given together
+resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"lifetime\": 10, \"expires_at\": $expires_at}" http://127.0.0.1:8080/add 2>/dev/null)
+if [[ $(echo "$resp" | jq -r .error.code) != "policy_violation" ]]; then
+    echo "LIFETIME AND EXPIRES AT FAILED: $resp"
+    exit 1
+fi
+
+# Check that waiting for activation takes from maximum lifetime (30 days by default)
+max_lifetime=2592000
+add_resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"lifetime\": $max_lifetime, \"not_before\": $(( $(date +%s) + max_lifetime / 2 ))}" http://127.0.0.1:8080/add 2>/dev/null)
+if (( $(echo "$add_resp" | jq -r '.expired - .created') > max_lifetime )); then
+    echo "MAX LIFETIME WITH NOT BEFORE FAILED: $add_resp"
+    exit 1
+fi
+
+# Check activation time in unix format
+not_before=$(( $(date +%s) + 2 ))
+add_resp=$(curl -d "{\"data\": \"$PAYLOAD\", \"lifetime\": 60, \"not_before\": $not_before}" http://127.0.0.1:8080/add 2>/dev/null)
+url=$(echo "$add_resp" | jq -r .msg)
+if [[ $(echo "$add_resp" | jq -r .expired) != $(( not_before + 60 )) ]]; then
+    echo "NOT BEFORE EXPIRY FAILED: $add_resp"
+    exit 1
+fi
+
+resp=$(send_get "$url")
+if [[ $(echo "$resp" | jq -r .error.code) != "not_yet_available" || $(echo "$resp" | jq -r .not_before) != "$not_before" ]]; then
+    echo "NOT YET AVAILABLE FAILED: $resp"
+    exit 1
+fi
+
+# Page tells when the secret becomes available and does not ask for a passphrase
+page=$(curl -X POST -H "Accept: text/html" "$url" 2>/dev/null)
+if ! echo "$page" | grep -q "$(date -u -d "@$not_before" +%Y-%m-%dT%H:%M:%SZ)" || echo "$page" | grep -q 'name="passphrase"'; then
+    echo "NOT YET AVAILABLE PAGE FAILED: $page"
+    exit 1
+fi
+
+resp=$(send_info "$url")
+if [[ $(echo "$resp" | jq -r .not_before) != "$not_before" || $(echo "$resp" | jq -r .clicks) != "1" ]]; then
+    echo "INFO FAILED: $resp"
+    exit 1
+fi
+
+sleep 4
+resp=$(send_get "$url")
+if [[ $(echo "$resp" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "GET AFTER NOT BEFORE FAILED: $resp"
+    exit 1
+fi
+
+resp=$(send_get "$expiring_url")
+if [[ $(echo "$resp" | jq -r .error.code) != "not_found" ]]; then
+    echo "GET AFTER EXPIRES AT FAILED: $resp"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,69 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+cat >> "$config_fn" <<EOF
+
+[sweeper]
+enabled = true
+interval = 1
+EOF
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check sweeper [$db_kind]:"
+
+metric() {
+    name=$1
+    value=$(curl http://127.0.0.1:8080/metrics 2>/dev/null | grep "^$name" | awk '{print $2}')
+    echo "$value"
+}
+
+for (( i = 0; i < 3; i++ )) do
+    send_add "$PAYLOAD" 1 1 > /dev/null
+done
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+sleep 4
+
+# Redis expires keys itself, so they are not counted
+if [ "$db_kind" != redis ] && [ "$(metric "onetimer_secrets_expired_total{engine=\"$db_kind\"}")" != 3 ]; then
+    echo "EXPIRED RECORDS ARE NOT PURGED"
+    exit 1
+fi
+if [ "$(metric "onetimer_secrets_exhausted_total{engine=\"$db_kind\"}")" != 0 ]; then
+    echo "EXPIRED RECORDS ARE COUNTED AS EXHAUSTED"
+    exit 1
+fi
+# Redis keyspace may be shared, so its records are not counted
+records=1
+if [ "$db_kind" == redis ]; then
+    records=""
+fi
+if [ "$(metric "onetimer_records{engine=\"$db_kind\"}")" != "$records" ]; then
+    echo "RECORDS GAUGE FAILED"
+    exit 1
+fi
+if [[ $(send_get "$url" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "LIVE RECORD IS PURGED"
+    exit 1
+fi
+echo OK
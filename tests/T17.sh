@@ -0,0 +1,78 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+echo "[$test_id] Check schema migrations [$db_kind]:"
+
+onetimer="$ROOT_DIR/target/release/onetimer"
+
+# Database created by the first release, before migrations were introduced
+if [ "$db_kind" == sqlite ]; then
+    rm -f ./db.sqlite
+    sqlite3 ./db.sqlite "CREATE TABLE msg (id TEXT NOT NULL, data TEXT, max_clicks BIGINT NOT NULL, created BIGINT NOT NULL, lifetime BIGINT NOT NULL);"
+    # Old schema had no primary key, so duplicate ids could be stored
+    sqlite3 ./db.sqlite "INSERT INTO msg VALUES ('dup', 'a', 1, strftime('%s', 'now'), 600), ('dup', 'b', 1, strftime('%s', 'now'), 600);"
+fi
+
+# Check migrate-only mode, it has to be safe to run it twice
+for (( i = 0; i < 2; i++ )) do
+    if ! "$onetimer" --migrate-only "$config_fn"; then
+        echo "MIGRATE ONLY FAILED"
+        exit 1
+    fi
+done
+
+# Run service on migrated database
+"$onetimer" "$config_fn" &
+pid=$!
+sleep 2
+
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+resp=$(send_get "$url")
+kill $pid
+wait $pid || true
+if [[ $(echo "$resp" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "GET AFTER MIGRATION FAILED: $resp"
+    exit 1
+fi
+
+# Check primary key and expiry index
+if [ "$db_kind" == sqlite ]; then
+    if [[ $(sqlite3 ./db.sqlite "SELECT COUNT(*) FROM msg WHERE id = 'dup';") != 1 ]]; then
+        echo "DUPLICATE IDS ARE NOT DROPPED"
+        exit 1
+    fi
+    if sqlite3 ./db.sqlite "INSERT INTO msg (id, data, max_clicks, created, lifetime, expires_at) VALUES ('dup', 'c', 1, 0, 60, 60);" 2>/dev/null; then
+        echo "DUPLICATE ID IS ACCEPTED"
+        exit 1
+    fi
+    if [[ -z $(sqlite3 ./db.sqlite "SELECT name FROM sqlite_master WHERE type = 'index' AND name = 'msg_expires_at';") ]]; then
+        echo "EXPIRY INDEX IS MISSING"
+        exit 1
+    fi
+fi
+
+# Check that database written by newer version is refused
+if [ "$db_kind" == sqlite ]; then
+    sqlite3 ./db.sqlite "INSERT INTO schema_version (version, applied) VALUES (9999, 0);"
+    if "$onetimer" --migrate-only "$config_fn"; then
+        echo "NEWER SCHEMA IS NOT REFUSED"
+        exit 1
+    fi
+fi
+echo OK
@@ -0,0 +1,84 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Only 256 possible ids, so collisions are inevitable
+cat >> "$config_fn" <<EOF
+
+[ids]
+length = 8
+alphabet = "01"
+
+[rate_limit]
+enabled = false
+burst = 1
+rate = 1.0
+max_not_found = 1
+ban_time = 1
+EOF
+
+onetimer="$ROOT_DIR/target/release/onetimer"
+rm -f ./onetimer.log
+
+# Run service
+"$onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check link id collisions [$db_kind]:"
+
+# Every secret has to be either stored under its own id or rejected, never overwritten
+declare -A urls
+for (( i = 0; i < 64; i++ )) do
+    resp=$(send_add "secret-$i" 1 60)
+    url=$(echo "$resp" | jq -r .msg)
+    if [[ "$url" == null ]]; then
+        if [[ $(echo "$resp" | jq -r .error.code) != internal_error ]]; then
+            echo "ADD FAILED: $resp"
+            exit 1
+        fi
+        continue
+    fi
+    if ! [[ "$url" =~ /get/[01]{8}/ ]]; then
+        echo "ID DOES NOT MATCH ALPHABET: $url"
+        exit 1
+    fi
+    urls[$i]=$url
+done
+
+for i in "${!urls[@]}"; do
+    data=$(send_get "${urls[$i]}" | jq -r .msg)
+    if [[ "$data" != "secret-$i" ]]; then
+        echo "SECRET IS OVERWRITTEN: ${urls[$i]} => $data instead of secret-$i"
+        exit 1
+    fi
+done
+
+if ! grep -q "Link id collision" ./onetimer.log; then
+    echo "NO COLLISIONS ARE RETRIED"
+    exit 1
+fi
+
+# Check that alphabet which is not url safe is refused
+sed -i 's|alphabet = "01"|alphabet = "01/"|' "$config_fn"
+if "$onetimer" --migrate-only "$config_fn" 2>/dev/null; then
+    echo "BAD ALPHABET IS NOT REFUSED"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,41 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check concurrent retrieval [$db_kind]:"
+
+# Single click secret has to be given out exactly once, however many requests race for it
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+for (( i = 0; i < 10; i++ )) do
+    curl -o /dev/null -w '%{http_code}\n' -H "Accept: application/json" "$url" 2>/dev/null > "./status_$i.txt" &
+done
+wait $(jobs -p | grep -v "^$pid$")
+
+ok=$(cat ./status_*.txt | grep -c '^200$' || true)
+not_found=$(cat ./status_*.txt | grep -c '^404$' || true)
+if [ "$ok" != 1 ] || [ "$not_found" != 9 ]; then
+    echo "CONCURRENT GET FAILED: $ok times 200, $not_found times 404"
+    exit 1
+fi
+echo OK
@@ -0,0 +1,62 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+# Only engines which keep data in local files can be inspected
+if [ "$db_kind" != file ] && [ "$db_kind" != sqlite ]; then
+    exit 0
+fi
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+rm -rf ./db ./db.sqlite
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check stored data [$db_kind]:"
+
+url=$( send_add "$PAYLOAD" 2 60 | jq -r .msg )
+key=${url##*/}
+id=${url%/*}
+id=${id##*/}
+
+if [ "$db_kind" == file ]; then
+    stored=(./db)
+else
+    stored=(./db.sqlite*)
+fi
+
+# Neither payload (also in base64 form used by file engine) nor link id and key may be found in storage
+for value in "$PAYLOAD" "$(echo -n "$PAYLOAD" | base64)" "$id" "$key"; do
+    if grep -raqF "$value" "${stored[@]}"; then
+        echo "STORED DATA CONTAINS '$value'"
+        exit 1
+    fi
+done
+if [ "$db_kind" == file ] && [ -e "./db/$id" ]; then
+    echo "FILE NAME IS LINK ID"
+    exit 1
+fi
+
+# Secret is still available after the checks
+if [[ $(send_get "$url" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "GET FAILED"
+    exit 1
+fi
+echo OK
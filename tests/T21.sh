@@ -0,0 +1,93 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+# TLS does not depend on database, so it is checked once
+if [ "$db_kind" != memory ]; then
+    exit 0
+fi
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+make_cert() {
+    openssl req -x509 -newkey rsa:2048 -nodes -days 1 -subj "/CN=$1" -keyout ./key.pem -out ./cert.pem 2>/dev/null
+}
+make_cert first
+
+cat >> "$config_fn" <<EOF
+
+[server]
+address = "https://127.0.0.1:8080"
+
+[server.tls]
+cert = "./cert.pem"
+key = "./key.pem"
+EOF
+
+# Run service
+"$ROOT_DIR/target/release/onetimer" "$config_fn" &
+pid=$!
+sleep 2
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check TLS [$db_kind]:"
+
+subject() {
+    curl -k -v -o /dev/null https://127.0.0.1:8080/healthz 2>&1 | grep -o "subject: CN *= *[a-z]*" | tr -d ' '
+}
+
+if [[ $(subject) != "subject:CN=first" ]]; then
+    echo "HTTPS FAILED"
+    exit 1
+fi
+url=$(curl -k -d "{\"data\": \"$PAYLOAD\"}" https://127.0.0.1:8080/add 2>/dev/null | jq -r .msg)
+if [[ $(curl -k -H "Accept: application/json" "$url" 2>/dev/null | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "GET OVER HTTPS FAILED"
+    exit 1
+fi
+
+# Check that certificate is reloaded on SIGHUP
+make_cert second
+kill -HUP $pid
+sleep 1
+if [[ $(subject) != "subject:CN=second" ]]; then
+    echo "CERTIFICATE RELOAD FAILED"
+    exit 1
+fi
+
+# Check that broken certificate is not loaded and the old one is kept
+echo broken > ./cert.pem
+kill -HUP $pid
+sleep 1
+if [[ $(subject) != "subject:CN=second" ]]; then
+    echo "BROKEN CERTIFICATE IS LOADED"
+    exit 1
+fi
+
+# Check that no client is refused or left without response while certificates are reloaded
+make_cert third
+for (( i = 0; i < 80; i++ )) do
+    curl -k -m 5 -o /dev/null -w '%{http_code}\n' https://127.0.0.1:8080/healthz 2>/dev/null > "./status_$i.txt" &
+done
+for (( i = 0; i < 10; i++ )) do
+    kill -HUP $pid
+done
+wait $(jobs -p | grep -v "^$pid$")
+if [[ $(cat ./status_*.txt | grep -c '^200$') != 80 ]]; then
+    echo "REQUESTS DURING RELOAD FAILED: $(cat ./status_*.txt | sort | uniq -c | tr '\n' ' ')"
+    exit 1
+fi
+echo OK
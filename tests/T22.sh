@@ -0,0 +1,63 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+# Memory database can not be shared, and redb file is locked by the process which has opened it
+if [ "$db_kind" == memory ] || [ "$db_kind" == redb ]; then
+    exit 0
+fi
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Run several services on the same database, so clicks are consumed by different engine instances
+# and nothing but the engine itself keeps them from racing
+pids=()
+for port in 8080 8081 8082 8083; do
+    sed "1i [server]\nport = $port\naddress = \"http://127.0.0.1:$port\"\n" "$config_fn" > "config_$port.toml"
+    sed -i "s|onetimer.log|onetimer_$port.log|" "config_$port.toml"
+    "$ROOT_DIR/target/release/onetimer" "config_$port.toml" &
+    pids+=($!)
+done
+sleep 2
+trap 'kill ${pids[*]}' EXIT
+
+echo "[$test_id] Check concurrent retrieval from several instances [$db_kind]:"
+
+# Every click has to be given out exactly once, whichever instance serves it
+check_clicks() {
+    clicks=$1
+    url=$( send_add "$PAYLOAD" "$clicks" 60 | jq -r .msg )
+    rm -f ./status_*.txt
+    jobs=()
+    for (( i = 0; i < 16; i++ )) do
+        port=$(( 8080 + i % 4 ))
+        curl -o /dev/null -w '%{http_code}\n' -H "Accept: application/json" "${url/:8080/:$port}" 2>/dev/null > "./status_$i.txt" &
+        jobs+=($!)
+    done
+    # Refused connections are reported below with the other unexpected statuses
+    wait "${jobs[@]}" || true
+
+    ok=$(cat ./status_*.txt | grep -c '^200$' || true)
+    not_found=$(cat ./status_*.txt | grep -c '^404$' || true)
+    if [ "$ok" != "$clicks" ] || [ "$not_found" != $(( 16 - clicks )) ]; then
+        echo "CONCURRENT GET FAILED: $ok times 200, $not_found times 404 for $clicks clicks"
+        exit 1
+    fi
+}
+
+check_clicks 1
+check_clicks 3
+echo OK
@@ -0,0 +1,72 @@
+#!/bin/bash
+
+# set -x
+set -e
+
+FILE=$(realpath "$0")
+tests_dir=$(dirname "$FILE")
+
+# shellcheck disable=SC1091
+source "$tests_dir/utils.sh"
+
+# Prepare work dir for current test
+test_id=$(basename "$0")
+db_kind=$1
+
+# Redis expires keys itself, so the sweeper has nothing to remove there
+if [ "$db_kind" == redis ]; then
+    exit 0
+fi
+
+config_fn="config_${db_kind}.toml"
+work_dir=$(prepare_env "$test_id" "$config_fn")
+cd "$work_dir"
+
+# Numbers of expired records removed by all sweeps so far
+removed() {
+    grep -o "Removed [0-9]* expired" ./onetimer.log | awk '{sum += $2} END {print sum + 0}'
+}
+
+run_service() {
+    "$ROOT_DIR/target/release/onetimer" "$1" &
+    pid=$!
+    sleep 2
+}
+trap 'kill $pid' EXIT
+
+echo "[$test_id] Check removal of unopened records [$db_kind]:"
+
+# Check that sweeper removes expired records which nobody has opened
+sed '/^\[log\]/i [sweeper]\nenabled = true\ninterval = 1\n' "$config_fn" > config_sweeper.toml
+run_service config_sweeper.toml
+for (( i = 0; i < 3; i++ )) do
+    send_add "$PAYLOAD" 1 1 > /dev/null
+done
+url=$( send_add "$PAYLOAD" 1 60 | jq -r .msg )
+sleep 4
+if [ "$(removed)" != 3 ]; then
+    echo "EXPIRED RECORDS ARE NOT REMOVED: $(removed)"
+    exit 1
+fi
+if [ "$db_kind" == file ] && [ "$(find ./db -type f | wc -l)" != 1 ]; then
+    echo "EXPIRED RECORD FILES ARE LEFT"
+    exit 1
+fi
+if [[ $(send_get "$url" | jq -r .msg) != "$PAYLOAD" ]]; then
+    echo "LIVE RECORD IS REMOVED"
+    exit 1
+fi
+kill $pid
+wait $pid || true
+
+# Check that nothing is removed with sweeper disabled
+rm -f ./onetimer.log
+sed '/^\[log\]/i [sweeper]\nenabled = false\n' "$config_fn" > config_no_sweeper.toml
+run_service config_no_sweeper.toml
+send_add "$PAYLOAD" 1 1 > /dev/null
+sleep 3
+if [ "$(removed)" != 0 ]; then
+    echo "DISABLED SWEEPER REMOVES RECORDS"
+    exit 1
+fi
+echo OK
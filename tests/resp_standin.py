@@ -0,0 +1,169 @@
+#!/usr/bin/env python3
+"""Minimal in-memory RESP server, a stand-in for redis-server in tests.
+
+Supports only commands used by `redis` engine of onetimer. Lua scripts are not
+interpreted: they are recognized by their first line (`-- onetimer:<name>`)
+and emulated with equivalent Python code.
+
+Usage: resp_standin.py [port]
+"""
+
+import hashlib
+import json
+import socketserver
+import sys
+import threading
+import time
+
+
+class Store:
+    def __init__(self):
+        self.lock = threading.Lock()
+        self.data = {}      # key -> (value, expires at)
+        self.scripts = {}   # sha1 -> script
+
+    def get(self, key):
+        value, expires = self.data.get(key, (None, None))
+        if expires is not None and expires <= time.time():
+            del self.data[key]
+            return None
+        return value
+
+    def set(self, key, value, ex=None, nx=False, keepttl=False):
+        if nx and self.get(key) is not None:
+            return False
+        expires = time.time() + ex if ex is not None else None
+        if keepttl and key in self.data:
+            expires = self.data[key][1]
+        self.data[key] = (value, expires)
+        return True
+
+    def delete(self, key):
+        return self.data.pop(key, None) is not None and 1 or 0
+
+
+STORE = Store()
+
+
+def script_consume(keys, args):
+    value = STORE.get(keys[0])
+    if value is None:
+        return None
+    r = json.loads(value)
+    if r["max_clicks"] <= 0:
+        return None
+    r["max_clicks"] -= 1
+    value = json.dumps(r).encode()
+    if r["max_clicks"] == 0:
+        STORE.delete(keys[0])
+    else:
+        STORE.set(keys[0], value, keepttl=True)
+    return value
+
+
+def script_fail_attempt(keys, args):
+    value = STORE.get(keys[0])
+    if value is None:
+        return None
+    r = json.loads(value)
+    r["attempts"] += 1
+    STORE.set(keys[0], json.dumps(r).encode(), keepttl=True)
+    return r["attempts"]
+
+
+SCRIPTS = {
+    "consume": script_consume,
+    "fail_attempt": script_fail_attempt,
+}
+
+
+class Error(Exception):
+    pass
+
+
+def run_script(script, numkeys, rest):
+    name = script.split(b"\n", 1)[0].decode().removeprefix("-- onetimer:").strip()
+    if name not in SCRIPTS:
+        raise Error("ERR unknown script")
+    numkeys = int(numkeys)
+    return SCRIPTS[name]([k.decode() for k in rest[:numkeys]], [a.decode() for a in rest[numkeys:]])
+
+
+def execute(cmd, args):
+    if cmd == "PING":
+        return "PONG"
+    if cmd == "GET":
+        return STORE.get(args[0].decode())
+    if cmd == "SET":
+        opts = [a.decode().upper() for a in args[2:]]
+        ex = int(opts[opts.index("EX") + 1]) if "EX" in opts else None
+        ok = STORE.set(args[0].decode(), args[1], ex=ex, nx="NX" in opts, keepttl="KEEPTTL" in opts)
+        return "OK" if ok else None
+    if cmd == "SELECT":
+        # There is only one database, tests use a single one anyway
+        return "OK"
+    if cmd == "FLUSHDB":
+        STORE.data.clear()
+        return "OK"
+    if cmd == "DEL":
+        return sum(STORE.delete(k.decode()) for k in args)
+    if cmd == "SCRIPT" and args[0].decode().upper() == "LOAD":
+        sha = hashlib.sha1(args[1]).hexdigest()
+        STORE.scripts[sha] = args[1]
+        return sha.encode()
+    if cmd == "EVAL":
+        return run_script(args[0], args[1], args[2:])
+    if cmd == "EVALSHA":
+        script = STORE.scripts.get(args[0].decode())
+        if script is None:
+            raise Error("NOSCRIPT No matching script. Please use EVAL.")
+        return run_script(script, args[1], args[2:])
+    raise Error("ERR unknown command '{}'".format(cmd))
+
+
+def encode(value):
+    if value is None:
+        return b"$-1\r\n"
+    if isinstance(value, int):
+        return b":%d\r\n" % value
+    if isinstance(value, str):
+        return b"+" + value.encode() + b"\r\n"
+    if isinstance(value, list):
+        return b"*%d\r\n" % len(value) + b"".join(encode(v) for v in value)
+    return b"$%d\r\n" % len(value) + value + b"\r\n"
+
+
+class Handler(socketserver.StreamRequestHandler):
+    def read_command(self):
+        line = self.rfile.readline()
+        if not line:
+            return None
+        count = int(line[1:])
+        args = []
+        for _ in range(count):
+            size = int(self.rfile.readline()[1:])
+            args.append(self.rfile.read(size + 2)[:-2])
+        return args
+
+    def handle(self):
+        while True:
+            command = self.read_command()
+            if command is None:
+                return
+            try:
+                with STORE.lock:
+                    reply = encode(execute(command[0].decode().upper(), command[1:]))
+            except Error as e:
+                reply = b"-" + str(e).encode() + b"\r\n"
+            self.wfile.write(reply)
+
+
+class Server(socketserver.ThreadingTCPServer):
+    allow_reuse_address = True
+    daemon_threads = True
+
+
+if __name__ == "__main__":
+    port = int(sys.argv[1]) if len(sys.argv) > 1 else 6379
+    with Server(("127.0.0.1", port), Handler) as server:
+        server.serve_forever()
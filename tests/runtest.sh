@@ -6,8 +6,16 @@ set -e
 FILE=$(realpath "$0")
 tests_dir=$(dirname "$FILE")
 
+# Run in-memory stand-in if there is no Redis to test against
+if ! (exec 3<>/dev/tcp/127.0.0.1/6379) 2>/dev/null; then
+    "$tests_dir/resp_standin.py" 6379 &
+    standin_pid=$!
+    trap 'kill $standin_pid' EXIT
+    sleep 1
+fi
+
 for f in "$tests_dir"/T*.sh; do
-    for db in "memory" "sqlite" "file" "mysql" "postgresql"; do
+    for db in "memory" "sqlite" "file" "redb" "redis" "mysql" "postgresql"; do
         "$f" $db
     done
 done
@@ -11,10 +11,37 @@ send_add() {
 
 send_get() {
     url=$1
+    resp=$(curl -H "Accept: application/json" "$url" 2>/dev/null)
+    echo "$resp"
+}
+
+send_info() {
+    url=${1/\/get\//\/info\/}
     resp=$(curl "$url" 2>/dev/null)
     echo "$resp"
 }
 
+send_delete() {
+    url=${1/\/get\//\/secret\/}
+    token=$2
+    resp=$(curl -X DELETE -H "X-Management-Token: $token" "${url%/*}" 2>/dev/null)
+    echo "$resp"
+}
+
+# Redis is shared between tests, so its database (the one from `config_redis.toml`)
+# is cleaned up before every test
+flush_redis() {
+    exec 3<>/dev/tcp/127.0.0.1/6379
+    printf '*2\r\n$6\r\nSELECT\r\n$2\r\n15\r\n*1\r\n$7\r\nFLUSHDB\r\n' >&3
+    read -r -u 3 _
+    read -r -u 3 _
+    exec 3>&-
+}
+
+redis_available() {
+    (exec 3<>/dev/tcp/127.0.0.1/6379) 2>/dev/null
+}
+
 prepare_env() {
     test_id=$1
     config_fn=$2
@@ -22,6 +49,9 @@ prepare_env() {
     mkdir -p "$test_dir"
 
     cp "$TESTS_DIR/$config_fn" "$test_dir"
+    if [ "$config_fn" == config_redis.toml ]; then
+        flush_redis
+    fi
 
     echo "$test_dir"
 }
@@ -37,3 +67,9 @@ export ROOT_DIR="$root_dir"
 export TESTS_DIR="$tests_dir"
 export WORK_DIR="$TESTS_DIR/_UNIVERSE_"
 
+# Tests are run with database kind as the first argument
+if [ "$1" == redis ] && ! redis_available; then
+    echo "[$(basename "$0")] Redis is not running at 127.0.0.1:6379, start redis-server or $TESTS_DIR/resp_standin.py"
+    exit 1
+fi
+